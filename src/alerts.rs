@@ -0,0 +1,683 @@
+use crate::config::{AppConfig, default_true};
+use crate::events::{Gap, GapsConfig};
+use crate::expr::{Expression, Scope};
+use crate::live::{Latest, Live};
+use crate::metrics::Metrics;
+use crate::notifiers::{self, Notification, NotifierConfig, Priority};
+use crate::trend::Recent;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Deserialize)]
+pub struct AlertsConfig {
+    /// Plain-text message for availability alerts, with `{source}`,
+    /// `{area_code}`, `{location}`, `{free_spaces}` and `{threshold}`
+    /// placeholders.
+    #[serde(default = "default_message")]
+    pub message: String,
+    /// Message for recovery notices, with the same placeholders.
+    #[serde(default = "default_recovery_message")]
+    pub recovery_message: String,
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+    #[serde(default)]
+    pub conditions: Vec<AlertCondition>,
+    #[serde(default)]
+    pub trends: Vec<TrendRule>,
+    /// Message for trend alerts, with the placeholders of `message` plus
+    /// `{lost}` and `{minutes}`.
+    #[serde(default = "default_trend_message")]
+    pub trend_message: String,
+    pub health: Option<HealthAlertConfig>,
+}
+
+fn default_message() -> String {
+    "{location} (area {area_code}) has {free_spaces} free spaces, below {threshold}".to_string()
+}
+
+fn default_trend_message() -> String {
+    "{location} (area {area_code}) lost {lost} spaces in {minutes} minutes, {free_spaces} left".to_string()
+}
+
+fn default_recovery_message() -> String {
+    "{location} (area {area_code}) is back to {free_spaces} free spaces".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AlertRule {
+    /// Restricts the rule to one source; applies to all sources if unset.
+    pub source: Option<String>,
+    pub area_code: i32,
+    /// Fires when free spaces drop below this value.
+    pub below: i64,
+    /// The rule re-arms only once free spaces climb back to
+    /// `below + hysteresis`, so values hovering around the threshold do not
+    /// trigger an alert on every tick.
+    #[serde(default)]
+    pub hysteresis: i64,
+    /// Minimum time between two alerts for this rule, even if it re-armed
+    /// in between. An alert held back by the cooldown is sent once it has
+    /// passed, if the rule is still firing.
+    #[serde(default)]
+    pub cooldown_secs: u64,
+    /// Repeats the alert this often while the rule keeps firing; only once
+    /// if unset.
+    pub repeat_secs: Option<u64>,
+    /// Sends a notice when free spaces climb back to `below + hysteresis`
+    /// after an alert went out.
+    #[serde(default = "default_true")]
+    pub notify_recovery: bool,
+    /// Names from `[notifiers]` to deliver through; all of them if unset.
+    pub notifiers: Option<Vec<String>>,
+}
+
+/// Alerts while an area is filling fast, e.g. losing 30 spaces within 10
+/// minutes.
+#[derive(Debug, Deserialize)]
+pub struct TrendRule {
+    /// Restricts the rule to one source; applies to all sources if unset.
+    pub source: Option<String>,
+    pub area_code: i32,
+    /// Fires when free spaces drop by at least this many within
+    /// `window_secs`, counted from the highest value in the window. Re-arms
+    /// once the loss within the window is smaller again.
+    pub drop: i64,
+    #[serde(default = "default_trend_window_secs")]
+    pub window_secs: u64,
+    #[serde(default)]
+    pub cooldown_secs: u64,
+    pub notifiers: Option<Vec<String>>,
+}
+
+fn default_trend_window_secs() -> u64 {
+    600
+}
+
+impl TrendRule {
+    fn matches(&self, source: &str, area_code: i32) -> bool {
+        self.area_code == area_code && self.source.as_deref().is_none_or(|s| s == source)
+    }
+}
+
+impl AlertRule {
+    fn matches(&self, source: &str, area_code: i32) -> bool {
+        self.area_code == area_code && self.source.as_deref().is_none_or(|s| s == source)
+    }
+}
+
+/// An alert on an [`Expression`] over any areas and the time of day,
+/// evaluated whenever a source has new values.
+#[derive(Debug, Deserialize)]
+pub struct AlertCondition {
+    pub name: String,
+    pub when: Expression,
+    /// Plain-text message, with `{name}` and `{when}` placeholders.
+    #[serde(default = "default_condition_message")]
+    pub message: String,
+    /// Minimum time between two alerts for this condition; an alert held
+    /// back is sent once it has passed, if the condition still holds.
+    #[serde(default)]
+    pub cooldown_secs: u64,
+    /// Sends a notice when the condition stops holding after an alert went
+    /// out.
+    #[serde(default = "default_true")]
+    pub notify_recovery: bool,
+    pub notifiers: Option<Vec<String>>,
+}
+
+fn default_condition_message() -> String {
+    "{name}: {when}".to_string()
+}
+
+/// High-priority alerts sent when a source keeps failing, either because
+/// the API or InfluxDB is unreachable, or when nothing has reached InfluxDB
+/// for a while.
+#[derive(Debug, Deserialize)]
+pub struct HealthAlertConfig {
+    /// Consecutive failed cycles before an alert is sent.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Alerts once InfluxDB has not accepted a write for this long, e.g.
+    /// while writes are only buffered or batches keep failing.
+    pub max_write_gap_secs: Option<u64>,
+    pub notifiers: Option<Vec<String>>,
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+pub struct Alert<'a> {
+    pub source: &'a str,
+    pub area_code: i32,
+    pub location: &'a str,
+    pub free_spaces: i64,
+}
+
+/// State of a firing rule; rules that are not firing are ok.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Firing {
+    /// The alert has not gone out yet because of the rule's cooldown.
+    Held,
+    Notified,
+}
+
+/// Per-source alert state and the HTTP client used to deliver alerts.
+#[derive(Default)]
+pub struct Alerter {
+    client: reqwest::Client,
+    /// Rules currently firing, keyed by area code and threshold.
+    firing: HashMap<(i32, i64), Firing>,
+    /// Trend rules currently firing, keyed by area code, drop and window.
+    trends_firing: HashMap<(i32, i64, u64), Firing>,
+    trends_sent: HashMap<(i32, i64, u64), Instant>,
+    last_sent: HashMap<(i32, i64), Instant>,
+    consecutive_failures: u32,
+    failure_notified: bool,
+}
+
+impl Alerter {
+    /// Evaluates every rule matching the observation. A rule that drops
+    /// below its threshold starts firing and alerts, unless its cooldown
+    /// holds the alert back until it has passed. While firing it re-alerts
+    /// every `repeat_secs` if set, and once availability recovers it sends a
+    /// recovery notice and returns to ok.
+    pub fn check(
+        &mut self,
+        config: &AlertsConfig,
+        notifiers: &HashMap<String, NotifierConfig>,
+        timezone: Tz,
+        alert: &Alert,
+    ) {
+        for (rule, notification) in self.evaluate(config, alert, Instant::now()) {
+            notifiers::dispatch(notifiers, rule.notifiers.as_deref(), &self.client, timezone, notification);
+        }
+    }
+
+    /// The notifications [`check`](Self::check) sends at `now`, with the
+    /// rule each belongs to.
+    fn evaluate<'a>(&mut self, config: &'a AlertsConfig, alert: &Alert, now: Instant) -> Vec<(&'a AlertRule, Notification)> {
+        let mut notifications = Vec::new();
+        for rule in config.rules.iter().filter(|r| r.matches(alert.source, alert.area_code)) {
+            let key = (rule.area_code, rule.below);
+            let vars = vec![
+                ("source", alert.source.to_string()),
+                ("area_code", alert.area_code.to_string()),
+                ("location", alert.location.to_string()),
+                ("free_spaces", alert.free_spaces.to_string()),
+                ("threshold", rule.below.to_string()),
+            ];
+
+            if alert.free_spaces < rule.below {
+                let last_sent = self.last_sent.get(&key).map(|at| now.saturating_duration_since(*at));
+                let due = match self.firing.get(&key) {
+                    None | Some(Firing::Held) => true,
+                    Some(Firing::Notified) => rule.repeat_secs
+                        .is_some_and(|repeat| last_sent.is_some_and(|elapsed| elapsed >= Duration::from_secs(repeat))),
+                };
+                if !due {
+                    continue;
+                }
+
+                let cooldown = Duration::from_secs(rule.cooldown_secs);
+                if last_sent.is_some_and(|elapsed| elapsed < cooldown) {
+                    if self.firing.insert(key, Firing::Held).is_none() {
+                        info!(
+                            "[{}] Area {} dropped below {} free spaces, alert held back by cooldown",
+                            alert.source, alert.area_code, rule.below,
+                        );
+                    }
+                    continue;
+                }
+
+                info!(
+                    "[{}] Area {} is below {} free spaces, sending alert",
+                    alert.source, alert.area_code, rule.below,
+                );
+                self.firing.insert(key, Firing::Notified);
+                self.last_sent.insert(key, now);
+
+                notifications.push((rule, Notification {
+                    title: format!("Low availability: {}", alert.location),
+                    message: substitute(&config.message, &vars),
+                    vars,
+                    priority: Priority::Normal,
+                }));
+            } else if alert.free_spaces >= rule.below + rule.hysteresis
+                && let Some(state) = self.firing.remove(&key)
+                && state == Firing::Notified
+                && rule.notify_recovery
+            {
+                info!(
+                    "[{}] Area {} is back to {} free spaces, sending recovery notice",
+                    alert.source, alert.area_code, alert.free_spaces,
+                );
+                notifications.push((rule, Notification {
+                    title: format!("Availability recovered: {}", alert.location),
+                    message: substitute(&config.recovery_message, &vars),
+                    vars,
+                    priority: Priority::Normal,
+                }));
+            }
+        }
+        notifications
+    }
+
+    /// Evaluates every trend rule matching the observation against the
+    /// area's recent values, alerting when one starts firing. Like
+    /// threshold rules, an alert held back by the cooldown is sent once it
+    /// has passed.
+    pub fn check_trends(
+        &mut self,
+        config: &AlertsConfig,
+        notifiers: &HashMap<String, NotifierConfig>,
+        timezone: Tz,
+        alert: &Alert,
+        recent: &Recent,
+    ) {
+        for rule in config.trends.iter().filter(|r| r.matches(alert.source, alert.area_code)) {
+            let key = (rule.area_code, rule.drop, rule.window_secs);
+            let window = Duration::from_secs(rule.window_secs);
+            let Some(lost) = recent.lost_within(alert.area_code, window) else {
+                continue;
+            };
+
+            if lost < rule.drop {
+                self.trends_firing.remove(&key);
+                continue;
+            }
+            if self.trends_firing.get(&key) == Some(&Firing::Notified) {
+                continue;
+            }
+
+            let cooldown = Duration::from_secs(rule.cooldown_secs);
+            if self.trends_sent.get(&key).is_some_and(|at| at.elapsed() < cooldown) {
+                if self.trends_firing.insert(key, Firing::Held).is_none() {
+                    info!(
+                        "[{}] Area {} lost {} spaces within {} s, alert held back by cooldown",
+                        alert.source, alert.area_code, lost, rule.window_secs,
+                    );
+                }
+                continue;
+            }
+
+            info!(
+                "[{}] Area {} lost {} spaces within {} s, sending alert",
+                alert.source, alert.area_code, lost, rule.window_secs,
+            );
+            self.trends_firing.insert(key, Firing::Notified);
+            self.trends_sent.insert(key, Instant::now());
+
+            let vars = vec![
+                ("source", alert.source.to_string()),
+                ("area_code", alert.area_code.to_string()),
+                ("location", alert.location.to_string()),
+                ("free_spaces", alert.free_spaces.to_string()),
+                ("lost", lost.to_string()),
+                ("minutes", rule.window_secs.div_ceil(60).to_string()),
+            ];
+            notifiers::dispatch(notifiers, rule.notifiers.as_deref(), &self.client, timezone, Notification {
+                title: format!("Filling fast: {}", alert.location),
+                message: substitute(&config.trend_message, &vars),
+                vars,
+                priority: Priority::Normal,
+            });
+        }
+    }
+
+    /// Notifies that an area had no written points for a while.
+    pub fn notify_gap(
+        &self,
+        config: &GapsConfig,
+        notifiers: &HashMap<String, NotifierConfig>,
+        timezone: Tz,
+        source: &str,
+        gap: &Gap,
+    ) {
+        let format = |at: DateTime<Utc>| at.with_timezone(&timezone).format("%Y-%m-%d %H:%M:%S").to_string();
+        notifiers::dispatch(notifiers, config.notifiers.as_deref(), &self.client, timezone, Notification {
+            title: format!("Data gap: {}", gap.location),
+            message: format!(
+                "{} (area {}) of source {} had no data for {:.0} s, from {} to {}.",
+                gap.location, gap.area_code, source, gap.duration_secs(), format(gap.start), format(gap.end),
+            ),
+            vars: vec![
+                ("source", source.to_string()),
+                ("area_code", gap.area_code.to_string()),
+                ("location", gap.location.clone()),
+            ],
+            priority: Priority::Normal,
+        });
+    }
+
+    /// Tracks consecutive failed cycles and alerts once the configured
+    /// threshold is reached, and again when the source recovers.
+    pub fn check_health(
+        &mut self,
+        config: &AlertsConfig,
+        notifiers: &HashMap<String, NotifierConfig>,
+        timezone: Tz,
+        source: &str,
+        result: &Result<()>,
+    ) {
+        let Some(health) = &config.health else {
+            return;
+        };
+        let names = health.notifiers.as_deref();
+
+        match result {
+            Ok(_) => {
+                if self.failure_notified {
+                    info!("[{}] Source recovered, sending alert", source);
+                    notifiers::dispatch(notifiers, names, &self.client, timezone, Notification {
+                        title: format!("{} recovered", source),
+                        message: format!(
+                            "Source {} is scraping successfully again after {} failed cycles.",
+                            source, self.consecutive_failures,
+                        ),
+                        vars: vec![("source", source.to_string())],
+                        priority: Priority::Normal,
+                    });
+                }
+                self.consecutive_failures = 0;
+                self.failure_notified = false;
+            }
+            Err(e) => {
+                self.consecutive_failures += 1;
+                if !self.failure_notified && self.consecutive_failures >= health.failure_threshold {
+                    warn!(
+                        "[{}] {} consecutive failed cycles, sending alert",
+                        source, self.consecutive_failures,
+                    );
+                    self.failure_notified = true;
+                    notifiers::dispatch(notifiers, names, &self.client, timezone, Notification {
+                        title: format!("{} is failing", source),
+                        message: format!(
+                            "Source {} has failed {} consecutive cycles.\n\nLast error: {:#}",
+                            source, self.consecutive_failures, e,
+                        ),
+                        vars: vec![("source", source.to_string())],
+                        priority: Priority::High,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn substitute(template: &str, vars: &[(&str, String)]) -> String {
+    let mut message = template.to_string();
+    for (name, value) in vars {
+        message = message.replace(&format!("{{{}}}", name), value);
+    }
+    message
+}
+
+/// State of every condition, shared by all sources since a condition may
+/// span several.
+#[derive(Default)]
+struct Conditions {
+    client: reqwest::Client,
+    firing: HashMap<String, Firing>,
+    last_sent: HashMap<String, Instant>,
+}
+
+impl Conditions {
+    fn check(
+        &mut self,
+        config: &AlertsConfig,
+        notifiers: &HashMap<String, NotifierConfig>,
+        timezone: Tz,
+        latest: &[Latest],
+    ) {
+        let scope = Scope { now: Utc::now().with_timezone(&timezone), latest };
+        for condition in &config.conditions {
+            let name = &condition.name;
+            let holds = match condition.when.eval(&scope) {
+                Ok(holds) => holds,
+                Err(e) => {
+                    debug!("Condition {} not evaluated: {:#}", name, e);
+                    continue;
+                }
+            };
+            let vars = vec![("name", name.clone()), ("when", condition.when.to_string())];
+
+            if holds {
+                if self.firing.get(name) == Some(&Firing::Notified) {
+                    continue;
+                }
+
+                let cooldown = Duration::from_secs(condition.cooldown_secs);
+                if self.last_sent.get(name).is_some_and(|at| at.elapsed() < cooldown) {
+                    if self.firing.insert(name.clone(), Firing::Held).is_none() {
+                        info!("Condition {} holds, alert held back by cooldown", name);
+                    }
+                    continue;
+                }
+
+                info!("Condition {} holds, sending alert", name);
+                self.firing.insert(name.clone(), Firing::Notified);
+                self.last_sent.insert(name.clone(), Instant::now());
+                notifiers::dispatch(notifiers, condition.notifiers.as_deref(), &self.client, timezone, Notification {
+                    title: format!("Alert: {}", name),
+                    message: substitute(&condition.message, &vars),
+                    vars,
+                    priority: Priority::Normal,
+                });
+            } else if let Some(state) = self.firing.remove(name)
+                && state == Firing::Notified
+                && condition.notify_recovery
+            {
+                info!("Condition {} no longer holds, sending recovery notice", name);
+                notifiers::dispatch(notifiers, condition.notifiers.as_deref(), &self.client, timezone, Notification {
+                    title: format!("Resolved: {}", name),
+                    message: format!("{} no longer holds: {}", name, condition.when),
+                    vars,
+                    priority: Priority::Normal,
+                });
+            }
+        }
+    }
+}
+
+/// Evaluates `[[alerts.conditions]]` every time a source publishes new
+/// samples, until `shutdown`.
+pub async fn watch_conditions(
+    config_rx: watch::Receiver<Arc<AppConfig>>,
+    live: Live,
+    shutdown: CancellationToken,
+) {
+    let mut samples = live.subscribe();
+    let mut conditions = Conditions::default();
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            received = samples.recv() => {
+                if let Err(RecvError::Closed) = received {
+                    return;
+                }
+            }
+        }
+        // A cycle publishes all of its samples at once; evaluate once for
+        // all of them.
+        while !matches!(samples.try_recv(), Err(TryRecvError::Empty | TryRecvError::Closed)) {}
+
+        let config = config_rx.borrow().clone();
+        if let Some(alerts) = &config.alerts
+            && !alerts.conditions.is_empty()
+        {
+            conditions.check(alerts, &config.notifiers, config.timezone, &live.latest());
+        }
+    }
+}
+
+/// How often [`watch_writes`] looks at the time of the last write.
+const WRITE_CHECK: Duration = Duration::from_secs(30);
+
+/// Sends a health alert once InfluxDB has not accepted a write for
+/// `max_write_gap_secs`, counting from startup, and a notice once writes
+/// succeed again. Runs until `shutdown`.
+pub async fn watch_writes(
+    config_rx: watch::Receiver<Arc<AppConfig>>,
+    metrics: Arc<Metrics>,
+    shutdown: CancellationToken,
+) {
+    let started = Utc::now();
+    let client = reqwest::Client::new();
+    let mut notified = false;
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = tokio::time::sleep(WRITE_CHECK) => {}
+        }
+
+        let config = config_rx.borrow().clone();
+        let Some(health) = config.alerts.as_ref().and_then(|alerts| alerts.health.as_ref()) else {
+            continue;
+        };
+        let Some(max_gap) = health.max_write_gap_secs.filter(|_| config.influxdb.enabled) else {
+            continue;
+        };
+        let last_write = metrics.influxdb_last_write();
+        let gap = (Utc::now() - last_write.unwrap_or(started)).to_std().unwrap_or_default();
+        let names = health.notifiers.as_deref();
+
+        if gap >= Duration::from_secs(max_gap) {
+            if notified {
+                continue;
+            }
+            warn!("No successful InfluxDB write for {} s, sending alert", gap.as_secs());
+            notified = true;
+            let since = match last_write {
+                Some(at) => format!("since {}", at.with_timezone(&config.timezone).format("%Y-%m-%d %H:%M:%S")),
+                None => "since startup".to_string(),
+            };
+            notifiers::dispatch(&config.notifiers, names, &client, config.timezone, Notification {
+                title: "InfluxDB writes are failing".to_string(),
+                message: format!("No points have reached InfluxDB for {} s ({}).", gap.as_secs(), since),
+                vars: Vec::new(),
+                priority: Priority::High,
+            });
+        } else if notified {
+            info!("InfluxDB is accepting writes again, sending alert");
+            notified = false;
+            notifiers::dispatch(&config.notifiers, names, &client, config.timezone, Notification {
+                title: "InfluxDB writes recovered".to_string(),
+                message: "Points are reaching InfluxDB again.".to_string(),
+                vars: Vec::new(),
+                priority: Priority::Normal,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(rule: serde_json::Value) -> AlertsConfig {
+        serde_json::from_value(serde_json::json!({ "rules": [rule] })).unwrap()
+    }
+
+    /// Titles of the notifications sent for `free_spaces` at `secs` seconds
+    /// after `start`.
+    fn titles(alerter: &mut Alerter, config: &AlertsConfig, start: Instant, secs: u64, free_spaces: i64) -> Vec<String> {
+        let alert = Alert { source: "default", area_code: 1, location: "A", free_spaces };
+        alerter.evaluate(config, &alert, start + Duration::from_secs(secs))
+            .into_iter()
+            .map(|(_, notification)| notification.title)
+            .collect()
+    }
+
+    const LOW: &str = "Low availability: A";
+    const RECOVERED: &str = "Availability recovered: A";
+
+    #[test]
+    fn cooldown_holds_then_releases() {
+        let config = config(serde_json::json!({ "area_code": 1, "below": 10, "cooldown_secs": 600 }));
+        let mut alerter = Alerter::default();
+        let start = Instant::now();
+
+        assert_eq!(titles(&mut alerter, &config, start, 0, 5), [LOW]);
+        assert_eq!(titles(&mut alerter, &config, start, 60, 20), [RECOVERED]);
+        // Fires again within the cooldown: held back.
+        assert!(titles(&mut alerter, &config, start, 120, 5).is_empty());
+        assert!(titles(&mut alerter, &config, start, 300, 5).is_empty());
+        // Sent once the cooldown has passed, as the rule still fires.
+        assert_eq!(titles(&mut alerter, &config, start, 600, 5), [LOW]);
+        assert!(titles(&mut alerter, &config, start, 660, 5).is_empty());
+    }
+
+    #[test]
+    fn repeats_while_firing() {
+        let config = config(serde_json::json!({ "area_code": 1, "below": 10, "repeat_secs": 300 }));
+        let mut alerter = Alerter::default();
+        let start = Instant::now();
+
+        assert_eq!(titles(&mut alerter, &config, start, 0, 5), [LOW]);
+        assert!(titles(&mut alerter, &config, start, 299, 5).is_empty());
+        assert_eq!(titles(&mut alerter, &config, start, 300, 5), [LOW]);
+        assert!(titles(&mut alerter, &config, start, 500, 5).is_empty());
+        assert_eq!(titles(&mut alerter, &config, start, 600, 5), [LOW]);
+    }
+
+    #[test]
+    fn only_once_without_repeat() {
+        let config = config(serde_json::json!({ "area_code": 1, "below": 10 }));
+        let mut alerter = Alerter::default();
+        let start = Instant::now();
+
+        assert_eq!(titles(&mut alerter, &config, start, 0, 5), [LOW]);
+        assert!(titles(&mut alerter, &config, start, 3600, 5).is_empty());
+    }
+
+    #[test]
+    fn hysteresis_rearms() {
+        let config = config(serde_json::json!({ "area_code": 1, "below": 10, "hysteresis": 5 }));
+        let mut alerter = Alerter::default();
+        let start = Instant::now();
+
+        assert_eq!(titles(&mut alerter, &config, start, 0, 9), [LOW]);
+        // Back above the threshold but not by the hysteresis: still firing.
+        assert!(titles(&mut alerter, &config, start, 60, 12).is_empty());
+        assert!(titles(&mut alerter, &config, start, 120, 9).is_empty());
+        // Re-armed at `below + hysteresis`, so the next drop alerts again.
+        assert_eq!(titles(&mut alerter, &config, start, 180, 15), [RECOVERED]);
+        assert_eq!(titles(&mut alerter, &config, start, 240, 9), [LOW]);
+    }
+
+    #[test]
+    fn no_recovery_after_held_alert() {
+        let config = config(serde_json::json!({ "area_code": 1, "below": 10, "cooldown_secs": 600 }));
+        let mut alerter = Alerter::default();
+        let start = Instant::now();
+
+        assert_eq!(titles(&mut alerter, &config, start, 0, 5), [LOW]);
+        assert_eq!(titles(&mut alerter, &config, start, 60, 20), [RECOVERED]);
+        assert!(titles(&mut alerter, &config, start, 120, 5).is_empty());
+        // The held alert never went out, so neither does a recovery notice.
+        assert!(titles(&mut alerter, &config, start, 180, 20).is_empty());
+        // Nor is it sent later, as the rule stopped firing.
+        assert!(titles(&mut alerter, &config, start, 700, 20).is_empty());
+    }
+
+    #[test]
+    fn recovery_notice_can_be_disabled() {
+        let config = config(serde_json::json!({ "area_code": 1, "below": 10, "notify_recovery": false }));
+        let mut alerter = Alerter::default();
+        let start = Instant::now();
+
+        assert_eq!(titles(&mut alerter, &config, start, 0, 5), [LOW]);
+        assert!(titles(&mut alerter, &config, start, 60, 20).is_empty());
+        assert_eq!(titles(&mut alerter, &config, start, 120, 5), [LOW]);
+    }
+}
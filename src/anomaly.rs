@@ -0,0 +1,98 @@
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+
+/// Values needed before the z-score rule applies; with fewer the standard
+/// deviation means little.
+const MIN_Z_SCORE_VALUES: usize = 5;
+
+/// Rules for spotting implausible values, such as an area going from 400
+/// free spaces to 3 in one interval. Negative values are always flagged.
+#[derive(Debug, Deserialize)]
+pub struct AnomalyConfig {
+    /// Flags a change of more than this many free spaces between two
+    /// consecutive scrapes.
+    pub max_delta: Option<i64>,
+    /// Flags a value more than this many standard deviations away from the
+    /// mean of the last `window` values.
+    pub z_score: Option<f64>,
+    #[serde(default = "default_window")]
+    pub window: usize,
+    #[serde(default)]
+    pub action: AnomalyAction,
+}
+
+fn default_window() -> usize {
+    20
+}
+
+/// What happens to a flagged point.
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnomalyAction {
+    /// Written with a `suspect=true` tag.
+    #[default]
+    Tag,
+    /// Not written at all.
+    Drop,
+}
+
+/// Recent values per area of one source.
+#[derive(Default)]
+pub struct Detector {
+    recent: HashMap<i32, VecDeque<i64>>,
+}
+
+impl Detector {
+    /// Describes why `value` looks implausible, if it does. Every
+    /// non-negative value joins the window afterwards, so a lasting change
+    /// in level is only flagged until the window has caught up.
+    pub fn check(&mut self, config: &AnomalyConfig, area_code: i32, value: i64) -> Option<String> {
+        if value < 0 {
+            return Some(format!("negative value {}", value));
+        }
+
+        let recent = self.recent.entry(area_code).or_default();
+        let reason = Self::reason(config, recent, value);
+
+        recent.push_back(value);
+        while recent.len() > config.window.max(MIN_Z_SCORE_VALUES) {
+            recent.pop_front();
+        }
+
+        reason
+    }
+
+    /// Like `check`, for a value sampled between two writes, which does not
+    /// join the window.
+    pub fn check_sample(&self, config: &AnomalyConfig, area_code: i32, value: i64) -> Option<String> {
+        if value < 0 {
+            return Some(format!("negative value {}", value));
+        }
+        Self::reason(config, self.recent.get(&area_code)?, value)
+    }
+
+    fn reason(config: &AnomalyConfig, recent: &VecDeque<i64>, value: i64) -> Option<String> {
+        if let (Some(max_delta), Some(&last)) = (config.max_delta, recent.back())
+            && (value - last).abs() > max_delta
+        {
+            return Some(format!("changed by {} since the last scrape ({} -> {})", value - last, last, value));
+        }
+
+        if let Some(threshold) = config.z_score
+            && recent.len() >= MIN_Z_SCORE_VALUES
+        {
+            let n = recent.len() as f64;
+            let mean = recent.iter().sum::<i64>() as f64 / n;
+            let variance = recent.iter().map(|&v| (v as f64 - mean).powi(2)).sum::<f64>() / n;
+            let std_dev = variance.sqrt();
+            if std_dev > 0.0 {
+                let z = (value as f64 - mean) / std_dev;
+                if z.abs() > threshold {
+                    return Some(format!("z-score {:.1} against the last {} values", z, recent.len()));
+                }
+            }
+        }
+
+        None
+    }
+}
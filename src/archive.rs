@@ -0,0 +1,255 @@
+use crate::config::SourceConfig;
+use crate::s3::{S3Client, S3Config};
+use crate::source::SourceType;
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, TimeDelta, Timelike, Utc};
+use chrono_tz::Tz;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+/// Keeps every raw API response, to derive new fields from the history
+/// later with `msparking backfill` or to look into upstream schema changes.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct ArchiveConfig {
+    #[serde(default = "crate::config::default_true")]
+    pub enabled: bool,
+    /// Holds one `<source>/YYYY/MM/DD` directory per source and local day.
+    pub directory: String,
+    /// Days to keep, including today. 0 keeps everything.
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u32,
+    /// Also uploads each finished hour of responses to this bucket, as one
+    /// `<source>/YYYY/MM/DD/HH.tar` object.
+    pub s3: Option<S3Config>,
+    /// Keeps uploaded responses on disk until `retention_days` is over,
+    /// instead of deleting them once uploaded.
+    #[serde(default)]
+    pub keep_uploaded: bool,
+}
+
+fn default_retention_days() -> u32 {
+    30
+}
+
+/// Writes one source's responses as gzip-compressed files named after the
+/// local time they were received at.
+pub struct Archive {
+    source: String,
+    directory: PathBuf,
+    extension: &'static str,
+    timezone: Tz,
+    retention_days: u32,
+    /// The local day old directories were last deleted on.
+    pruned: Mutex<Option<NaiveDate>>,
+    s3: Option<Arc<S3Client>>,
+    keep_uploaded: bool,
+    /// The local hour finished hours were last looked for to upload.
+    uploaded: Mutex<Option<(NaiveDate, u32)>>,
+}
+
+/// Keeps uploads of archives rebuilt by a reload from overlapping.
+static UPLOADING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+impl Archive {
+    pub fn new(config: &ArchiveConfig, source: &SourceConfig, timezone: Tz) -> Result<Self> {
+        let extension = match source.kind {
+            SourceType::Html => "html",
+            SourceType::Xml => "xml",
+            _ => "json",
+        };
+
+        let s3 = config.s3.as_ref().map(S3Client::new).transpose()?.map(Arc::new);
+        Ok(Archive {
+            source: source.name.clone(),
+            directory: Path::new(&config.directory).join(&source.name),
+            extension,
+            timezone,
+            retention_days: config.retention_days,
+            pruned: Mutex::new(None),
+            s3,
+            keep_uploaded: config.keep_uploaded,
+            uploaded: Mutex::new(None),
+        })
+    }
+
+    /// Archiving is best effort; a failure is logged and never fails the
+    /// fetch.
+    pub fn save(&self, body: &str) {
+        if let Err(e) = self.write(body) {
+            warn!("[{}] Failed to archive API response: {:#}", self.source, e);
+        }
+
+        let local = Utc::now().with_timezone(&self.timezone);
+        let today = local.date_naive();
+        if let Some(s3) = &self.s3 {
+            let hour = (today, local.hour());
+            let mut uploaded = self.uploaded.lock().unwrap();
+            if *uploaded != Some(hour) {
+                *uploaded = Some(hour);
+                let upload = Upload {
+                    s3: s3.clone(),
+                    source: self.source.clone(),
+                    directory: self.directory.clone(),
+                    keep: self.keep_uploaded,
+                    current: hour,
+                };
+                tokio::spawn(upload.run());
+            }
+        }
+
+        let mut pruned = self.pruned.lock().unwrap();
+        if self.retention_days > 0 && *pruned != Some(today) {
+            *pruned = Some(today);
+            match self.prune(today) {
+                Ok(0) => {}
+                Ok(days) => info!("[{}] Deleted {} archived days past the {} day retention", self.source, days, self.retention_days),
+                Err(e) => warn!("[{}] Failed to delete old archived responses: {:#}", self.source, e),
+            }
+        }
+    }
+
+    fn write(&self, body: &str) -> Result<()> {
+        let local = Utc::now().with_timezone(&self.timezone);
+        let directory = self.directory.join(local.format("%Y/%m/%d").to_string());
+        fs::create_dir_all(&directory)
+            .with_context(|| format!("Failed to create archive directory {}", directory.display()))?;
+
+        let path = directory.join(format!("{}.{}.gz", local.format("%H%M%S%.3f"), self.extension));
+        let file = fs::File::create(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(body.as_bytes())
+            .and_then(|_| encoder.finish().map(drop))
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Deletes the day directories that fell out of the retention, then the
+    /// month and year directories left empty. Returns the days deleted.
+    fn prune(&self, today: NaiveDate) -> Result<usize> {
+        let oldest = today - TimeDelta::days(i64::from(self.retention_days) - 1);
+        let mut deleted = 0;
+
+        for year in subdirectories(&self.directory)? {
+            for month in subdirectories(&year)? {
+                for day in subdirectories(&month)? {
+                    let date = day.strip_prefix(&self.directory).ok()
+                        .and_then(|relative| relative.to_str())
+                        .and_then(|relative| NaiveDate::parse_from_str(relative, "%Y/%m/%d").ok());
+                    if date.is_some_and(|date| date < oldest) {
+                        fs::remove_dir_all(&day)
+                            .with_context(|| format!("Failed to delete {}", day.display()))?;
+                        deleted += 1;
+                    }
+                }
+                // Fails harmlessly while the month still has days.
+                let _ = fs::remove_dir(&month);
+            }
+            let _ = fs::remove_dir(&year);
+        }
+        Ok(deleted)
+    }
+}
+
+/// Uploads every finished hour of a source's archive not uploaded yet,
+/// including those left over from earlier runs or failed uploads.
+struct Upload {
+    s3: Arc<S3Client>,
+    source: String,
+    directory: PathBuf,
+    keep: bool,
+    /// Still being written to.
+    current: (NaiveDate, u32),
+}
+
+impl Upload {
+    async fn run(self) {
+        let _uploading = UPLOADING.lock().await;
+        if let Err(e) = self.upload().await {
+            warn!("[{}] Failed to upload archived responses to S3, retrying next hour: {:#}", self.source, e);
+        }
+    }
+
+    async fn upload(&self) -> Result<()> {
+        for year in subdirectories(&self.directory)? {
+            for month in subdirectories(&year)? {
+                for day in subdirectories(&month)? {
+                    let Some(relative) = day.strip_prefix(&self.directory).ok().and_then(|relative| relative.to_str()) else {
+                        continue;
+                    };
+                    let Ok(date) = NaiveDate::parse_from_str(relative, "%Y/%m/%d") else {
+                        continue;
+                    };
+                    for (hour, files) in self.pending(&day, date)? {
+                        let key = format!("{}/{}/{:02}.tar", self.source, relative, hour);
+                        self.s3.put(&key, pack(&files)?).await?;
+                        info!("[{}] Uploaded {} archived responses to S3 as {}", self.source, files.len(), key);
+                        self.done(&day, hour, &files)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Responses of the finished hours in `day` not uploaded yet.
+    fn pending(&self, day: &Path, date: NaiveDate) -> Result<BTreeMap<u32, Vec<PathBuf>>> {
+        let mut hours: BTreeMap<u32, Vec<PathBuf>> = BTreeMap::new();
+        for entry in fs::read_dir(day).with_context(|| format!("Failed to read {}", day.display()))? {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            let Some(hour) = name.get(..2).and_then(|hour| hour.parse().ok()) else {
+                continue;
+            };
+            if name.ends_with(".gz") && (date, hour) != self.current && !day.join(marker(hour)).exists() {
+                hours.entry(hour).or_default().push(path);
+            }
+        }
+        Ok(hours)
+    }
+
+    /// Deletes uploaded responses, or marks their hour as uploaded when
+    /// they are kept.
+    fn done(&self, day: &Path, hour: u32, files: &[PathBuf]) -> Result<()> {
+        if self.keep {
+            let path = day.join(marker(hour));
+            return fs::write(&path, "").with_context(|| format!("Failed to create {}", path.display()));
+        }
+        for file in files {
+            fs::remove_file(file).with_context(|| format!("Failed to delete {}", file.display()))?;
+        }
+        Ok(())
+    }
+}
+
+fn marker(hour: u32) -> String {
+    format!("{:02}.uploaded", hour)
+}
+
+/// An uncompressed tar of the (already compressed) responses.
+fn pack(files: &[PathBuf]) -> Result<Vec<u8>> {
+    let mut tar = tar::Builder::new(Vec::new());
+    for file in files {
+        let name = file.file_name().unwrap_or_default();
+        tar.append_path_with_name(file, name)
+            .with_context(|| format!("Failed to add {} to the upload", file.display()))?;
+    }
+    tar.into_inner().context("Failed to build the upload")
+}
+
+fn subdirectories(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let entries = fs::read_dir(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect())
+}
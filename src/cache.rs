@@ -0,0 +1,65 @@
+use crate::config::CacheConfig;
+use crate::source::AreaData;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// The last non-zero value of an area and when it was scraped.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CachedArea {
+    #[serde(flatten)]
+    pub data: AreaData,
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl CachedArea {
+    /// Whether the value is recent enough to be written, given the source's
+    /// `max_cache_age_secs`.
+    pub fn is_fresh(&self, max_age_secs: Option<u64>, now: DateTime<Utc>) -> bool {
+        max_age_secs.is_none_or(|secs| (now - self.fetched_at).num_seconds() <= secs as i64)
+    }
+}
+
+/// The cached areas of one source, stored as JSON.
+pub struct CacheFile {
+    path: PathBuf,
+}
+
+impl CacheFile {
+    pub fn new(config: &CacheConfig, source: &str) -> Self {
+        let file = format!("{}.json", source.replace(['/', '\\'], "_"));
+        CacheFile { path: PathBuf::from(&config.dir).join(file) }
+    }
+
+    /// Returns an empty cache if nothing has been saved yet.
+    pub fn load(&self) -> Result<HashMap<i32, CachedArea>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read cache file {}", self.path.display())),
+        };
+
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse cache file {}", self.path.display()))
+    }
+
+    /// Writes to a temporary file first so a crash never leaves a truncated
+    /// cache behind.
+    pub fn save(&self, data: &HashMap<i32, CachedArea>) -> Result<()> {
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create cache directory {}", parent.display()))?;
+        }
+
+        let contents = serde_json::to_string(data).context("Failed to serialize cache")?;
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, contents)
+            .with_context(|| format!("Failed to write cache file {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to replace cache file {}", self.path.display()))
+    }
+}
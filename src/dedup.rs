@@ -0,0 +1,45 @@
+use crate::sink::Sample;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Skips writing areas whose free spaces have not changed since the last
+/// write.
+#[derive(Debug, Deserialize)]
+pub struct DedupConfig {
+    /// Writes every Nth unchanged sample anyway, so a quiet area still shows
+    /// up in queries over short ranges. 0 never writes unchanged samples.
+    #[serde(default)]
+    pub heartbeat_every: u32,
+}
+
+/// Last written value per area of one source, and how many unchanged
+/// samples were skipped since.
+#[derive(Default)]
+pub struct Dedup {
+    written: HashMap<i32, (i64, u32)>,
+}
+
+impl Dedup {
+    /// Splits the samples into those to write and the unchanged ones.
+    pub fn split(&self, config: &DedupConfig, samples: Vec<Sample>) -> (Vec<Sample>, Vec<Sample>) {
+        samples.into_iter().partition(|sample| match self.written.get(&sample.area_code) {
+            Some(&(value, skipped)) if value == sample.free_spaces => {
+                config.heartbeat_every > 0 && skipped + 1 >= config.heartbeat_every
+            }
+            _ => true,
+        })
+    }
+
+    /// Records a successful write. State is left alone when a write fails,
+    /// so the same values are tried again next cycle.
+    pub fn record(&mut self, written: &[Sample], unchanged: &[Sample]) {
+        for sample in written {
+            self.written.insert(sample.area_code, (sample.free_spaces, 0));
+        }
+        for sample in unchanged {
+            if let Some((_, skipped)) = self.written.get_mut(&sample.area_code) {
+                *skipped += 1;
+            }
+        }
+    }
+}
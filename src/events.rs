@@ -0,0 +1,175 @@
+use crate::sink::Sample;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use influxdb2::models::DataPoint;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// A period in which a source's points are cached or missing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Inside this maintenance window.
+    Maintenance(String),
+    /// Fetching from the API fails.
+    Outage,
+    /// Scraping is paused.
+    Paused,
+}
+
+impl Event {
+    fn kind(&self) -> &'static str {
+        match self {
+            Event::Maintenance(_) => "maintenance",
+            Event::Outage => "outage",
+            Event::Paused => "paused",
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            Event::Maintenance(window) => format!("Maintenance window {}", window),
+            Event::Outage => "Upstream outage".to_string(),
+            Event::Paused => "Scraping paused".to_string(),
+        }
+    }
+}
+
+/// The event one source is in, turned into `parking_events` points as it
+/// starts and ends.
+#[derive(Default)]
+pub struct Events {
+    active: Option<(Event, DateTime<Utc>)>,
+}
+
+impl Events {
+    /// Moves to `next`, returning a point for the end of the previous event
+    /// and one for the start of the new one as far as they changed.
+    pub fn update(
+        &mut self,
+        source: &str,
+        tags: Option<&HashMap<String, String>>,
+        next: Option<Event>,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<DataPoint>> {
+        if self.active.as_ref().map(|(event, _)| event) == next.as_ref() {
+            return Ok(Vec::new());
+        }
+
+        let mut points = Vec::new();
+        if let Some((event, since)) = self.active.take() {
+            let duration = (now - since).as_seconds_f64();
+            points.push(to_data_point(source, tags, &event, false, Some(duration), now)?);
+        }
+        if let Some(event) = next {
+            points.push(to_data_point(source, tags, &event, true, None, now)?);
+            self.active = Some((event, now));
+        }
+        Ok(points)
+    }
+}
+
+fn to_data_point(
+    source: &str,
+    tags: Option<&HashMap<String, String>>,
+    event: &Event,
+    active: bool,
+    duration_secs: Option<f64>,
+    at: DateTime<Utc>,
+) -> Result<DataPoint> {
+    let mut builder = DataPoint::builder("parking_events")
+        .tag("source", source)
+        .tag("event", event.kind());
+    for (key, value) in tags.into_iter().flatten() {
+        builder = builder.tag(key, value);
+    }
+
+    if let Some(duration) = duration_secs {
+        builder = builder.field("duration_secs", duration);
+    }
+
+    builder
+        .field("active", active)
+        .field("text", event.text())
+        .timestamp(at.timestamp_nanos_opt().unwrap())
+        .build()
+        .context("Failed to build parking_events point")
+}
+
+/// Reports areas that went without a written point for a while, once
+/// points arrive again.
+#[derive(Debug, Deserialize, Clone)]
+pub struct GapsConfig {
+    /// Shortest time between two written points of an area that counts as
+    /// a gap.
+    pub min_secs: u64,
+    /// Also notifies when a gap closes, through the named `notifiers` or
+    /// all of them if omitted.
+    #[serde(default)]
+    pub notify: bool,
+    pub notifiers: Option<Vec<String>>,
+}
+
+/// A stretch without written points for one area, from its last point
+/// before to its first point after.
+pub struct Gap {
+    pub area_code: i32,
+    pub location: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Gap {
+    pub fn duration_secs(&self) -> f64 {
+        (self.end - self.start).as_seconds_f64()
+    }
+
+    /// A finished `parking_events` point like those of [`Events`], at the
+    /// end of the gap.
+    pub fn to_data_point(&self, source: &str, tags: Option<&HashMap<String, String>>) -> Result<DataPoint> {
+        let mut builder = DataPoint::builder("parking_events")
+            .tag("source", source)
+            .tag("event", "gap")
+            .tag("area_code", self.area_code.to_string())
+            .tag("location", &self.location);
+        for (key, value) in tags.into_iter().flatten() {
+            builder = builder.tag(key, value);
+        }
+
+        builder
+            .field("active", false)
+            .field("text", format!("No data for {}", self.location))
+            .field("start", self.start.to_rfc3339())
+            .field("duration_secs", self.duration_secs())
+            .timestamp(self.end.timestamp_nanos_opt().unwrap())
+            .build()
+            .context("Failed to build parking_events point")
+    }
+}
+
+/// When each area of one source last had a point written.
+#[derive(Default)]
+pub struct Gaps {
+    written: HashMap<i32, DateTime<Utc>>,
+}
+
+impl Gaps {
+    /// Records the samples of a successful write, returning the gaps they
+    /// close. Samples left out as unchanged count as written.
+    pub fn record<'a>(&mut self, config: &GapsConfig, samples: impl IntoIterator<Item = &'a Sample>) -> Vec<Gap> {
+        let mut gaps = Vec::new();
+        for sample in samples {
+            let previous = self.written.insert(sample.area_code, sample.timestamp);
+            if let Some(start) = previous
+                && (sample.timestamp - start).num_seconds() >= config.min_secs as i64
+            {
+                gaps.push(Gap {
+                    area_code: sample.area_code,
+                    location: sample.location.clone(),
+                    start,
+                    end: sample.timestamp,
+                });
+            }
+        }
+        gaps
+    }
+}
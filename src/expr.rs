@@ -0,0 +1,592 @@
+use crate::live::Latest;
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Datelike, Timelike};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// A condition over the latest scraped values and the local time, such as
+/// `area(12).free < 15 && hour >= 7 && hour <= 9`.
+///
+/// `area(code)` is the most recently scraped area with that code in any
+/// source, `area("source", code)` the one in the named source. Areas have
+/// the fields `free`, `total`, `occupied`, `occupancy` (percent), `delta`
+/// and `predicted`. `hour`, `minute` and `weekday` (1 for Monday to 7 for
+/// Sunday) are in the top-level timezone. Numbers combine with `+ - * /`,
+/// compare with `== != < <= > >=`, and conditions with `&& || !`.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    text: String,
+    root: Node,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Number(f64),
+    Bool(bool),
+    Var(Var),
+    Area {
+        source: Option<String>,
+        area_code: i32,
+        field: Field,
+    },
+    Not(Box<Node>),
+    Neg(Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Var {
+    Hour,
+    Minute,
+    Weekday,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Free,
+    Total,
+    Occupied,
+    Occupancy,
+    Delta,
+    Predicted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Number,
+    Bool,
+}
+
+/// What an expression is evaluated against.
+pub struct Scope<'a> {
+    pub now: DateTime<Tz>,
+    pub latest: &'a [Latest],
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Self> {
+        let root = parse(text).with_context(|| format!("Invalid expression {:?}", text))?;
+        Ok(Expression { text: text.to_string(), root })
+    }
+
+    pub fn eval(&self, scope: &Scope) -> Result<bool> {
+        match self.root.eval(scope)? {
+            Value::Bool(value) => Ok(value),
+            Value::Number(_) => unreachable!("checked by parse"),
+        }
+    }
+
+    /// Every area the expression refers to, with its source if given.
+    pub fn areas(&self) -> Vec<(Option<&str>, i32)> {
+        let mut areas = Vec::new();
+        self.root.areas(&mut areas);
+        areas
+    }
+}
+
+impl std::fmt::Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl<'de> Deserialize<'de> for Expression {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Expression::parse(&text).map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Value {
+    Number(f64),
+    Bool(bool),
+}
+
+impl Value {
+    fn number(self) -> f64 {
+        match self {
+            Value::Number(value) => value,
+            Value::Bool(_) => unreachable!("checked by parse"),
+        }
+    }
+
+    fn bool(self) -> bool {
+        match self {
+            Value::Bool(value) => value,
+            Value::Number(_) => unreachable!("checked by parse"),
+        }
+    }
+}
+
+impl Node {
+    fn kind(&self) -> Kind {
+        match self {
+            Node::Number(_) | Node::Var(_) | Node::Area { .. } | Node::Neg(_) => Kind::Number,
+            Node::Bool(_) | Node::Not(_) => Kind::Bool,
+            Node::Binary(op, _, _) => match op {
+                Op::Add | Op::Sub | Op::Mul | Op::Div => Kind::Number,
+                _ => Kind::Bool,
+            },
+        }
+    }
+
+    fn eval(&self, scope: &Scope) -> Result<Value> {
+        Ok(match self {
+            Node::Number(value) => Value::Number(*value),
+            Node::Bool(value) => Value::Bool(*value),
+            Node::Var(var) => Value::Number(match var {
+                Var::Hour => scope.now.hour() as f64,
+                Var::Minute => scope.now.minute() as f64,
+                Var::Weekday => scope.now.weekday().number_from_monday() as f64,
+            }),
+            Node::Area { source, area_code, field } => Value::Number(area(scope, source.as_deref(), *area_code, *field)?),
+            Node::Not(node) => Value::Bool(!node.eval(scope)?.bool()),
+            Node::Neg(node) => Value::Number(-node.eval(scope)?.number()),
+            // Short-circuit so `area("a", 1).free < 5 || ...` works while
+            // only some areas have data.
+            Node::Binary(Op::And, left, right) => Value::Bool(left.eval(scope)?.bool() && right.eval(scope)?.bool()),
+            Node::Binary(Op::Or, left, right) => Value::Bool(left.eval(scope)?.bool() || right.eval(scope)?.bool()),
+            Node::Binary(Op::Eq, left, right) if left.kind() == Kind::Bool => {
+                Value::Bool(left.eval(scope)?.bool() == right.eval(scope)?.bool())
+            }
+            Node::Binary(Op::Ne, left, right) if left.kind() == Kind::Bool => {
+                Value::Bool(left.eval(scope)?.bool() != right.eval(scope)?.bool())
+            }
+            Node::Binary(op, left, right) => {
+                let (a, b) = (left.eval(scope)?.number(), right.eval(scope)?.number());
+                match op {
+                    Op::Add => Value::Number(a + b),
+                    Op::Sub => Value::Number(a - b),
+                    Op::Mul => Value::Number(a * b),
+                    Op::Div => Value::Number(a / b),
+                    Op::Eq => Value::Bool(a == b),
+                    Op::Ne => Value::Bool(a != b),
+                    Op::Lt => Value::Bool(a < b),
+                    Op::Le => Value::Bool(a <= b),
+                    Op::Gt => Value::Bool(a > b),
+                    Op::Ge => Value::Bool(a >= b),
+                    Op::And | Op::Or => unreachable!("handled above"),
+                }
+            }
+        })
+    }
+
+    fn areas<'a>(&'a self, areas: &mut Vec<(Option<&'a str>, i32)>) {
+        match self {
+            Node::Area { source, area_code, .. } => areas.push((source.as_deref(), *area_code)),
+            Node::Not(node) | Node::Neg(node) => node.areas(areas),
+            Node::Binary(_, left, right) => {
+                left.areas(areas);
+                right.areas(areas);
+            }
+            Node::Number(_) | Node::Bool(_) | Node::Var(_) => {}
+        }
+    }
+}
+
+fn area(scope: &Scope, source: Option<&str>, area_code: i32, field: Field) -> Result<f64> {
+    let latest = scope.latest.iter()
+        .map(|latest| &latest.sample)
+        .filter(|sample| sample.area_code == area_code && source.is_none_or(|source| sample.source == source))
+        .max_by_key(|sample| sample.timestamp)
+        .ok_or_else(|| match source {
+            Some(source) => anyhow!("no data for area {} of {} yet", area_code, source),
+            None => anyhow!("no data for area {} yet", area_code),
+        })?;
+
+    let value = match field {
+        Field::Free => Some(latest.free_spaces as f64),
+        Field::Total => latest.total_spaces.map(|total| total as f64),
+        Field::Occupied => latest.total_spaces.map(|total| (total - latest.free_spaces) as f64),
+        Field::Occupancy => latest.occupancy_pct,
+        Field::Delta => latest.delta_spaces,
+        Field::Predicted => latest.predicted_free_spaces,
+    };
+    value.ok_or_else(|| anyhow!("area {} has no {} value", area_code, field.name()))
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "free" => Field::Free,
+            "total" => Field::Total,
+            "occupied" => Field::Occupied,
+            "occupancy" => Field::Occupancy,
+            "delta" => Field::Delta,
+            "predicted" => Field::Predicted,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Free => "free",
+            Field::Total => "total",
+            Field::Occupied => "occupied",
+            Field::Occupancy => "occupancy",
+            Field::Delta => "delta",
+            Field::Predicted => "predicted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Str(String),
+    Symbol(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Str(value) => write!(f, "{:?}", value),
+            Token::Symbol(symbol) => write!(f, "{:?}", symbol),
+        }
+    }
+}
+
+/// Longest first, so `<=` is not read as `<` followed by `=`.
+const SYMBOLS: [&str; 17] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "+", "-", "*", "/", "(", ")", ",", ".",
+];
+
+fn parse(text: &str) -> Result<Node> {
+    let mut parser = Parser { tokens: tokenize(text)?, pos: 0 };
+    let root = parser.or()?;
+    if let Some(token) = parser.peek() {
+        bail!("Unexpected {}", token);
+    }
+    if root.kind() != Kind::Bool {
+        bail!("Evaluates to a number, not a condition");
+    }
+    Ok(root)
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            let end = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+            let number = rest[..end].parse()
+                .map_err(|_| anyhow!("Invalid number {}", &rest[..end]))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if c == '"' {
+            let end = rest[1..].find('"')
+                .ok_or_else(|| anyhow!("Unterminated string"))?;
+            tokens.push(Token::Str(rest[1..end + 1].to_string()));
+            rest = &rest[end + 2..];
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else {
+            bail!("Unexpected {:?}", c);
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent, one method per precedence level from loosest to
+/// tightest binding.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self.tokens.get(self.pos).cloned().ok_or_else(|| anyhow!("Unexpected end of expression"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        if !self.eat(symbol) {
+            match self.peek() {
+                Some(token) => bail!("Expected {:?}, found {}", symbol, token),
+                None => bail!("Expected {:?} at the end of the expression", symbol),
+            }
+        }
+        Ok(())
+    }
+
+    /// Consumes the first of `ops` that comes next.
+    fn op(&mut self, ops: &[Op]) -> Option<Op> {
+        ops.iter().copied().find(|op| self.eat(op.symbol()))
+    }
+
+    /// Parses operands with `operand` for as long as one of `ops` follows,
+    /// left to right.
+    fn binary(&mut self, ops: &[Op], operand: fn(&mut Self) -> Result<Node>) -> Result<Node> {
+        let mut left = operand(self)?;
+        while let Some(op) = self.op(ops) {
+            let right = operand(self)?;
+            left = binary(op, left, right)?;
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Node> {
+        self.binary(&[Op::Or], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Node> {
+        self.binary(&[Op::And], Self::comparison)
+    }
+
+    /// Comparisons do not chain, `1 < x < 3` is an error.
+    fn comparison(&mut self) -> Result<Node> {
+        const COMPARISONS: [Op; 6] = [Op::Eq, Op::Ne, Op::Le, Op::Ge, Op::Lt, Op::Gt];
+        let left = self.sum()?;
+        match self.op(&COMPARISONS) {
+            Some(op) => {
+                let right = self.sum()?;
+                if self.op(&COMPARISONS).is_some() {
+                    bail!("Comparisons cannot be chained, combine them with &&");
+                }
+                binary(op, left, right)
+            }
+            None => Ok(left),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Node> {
+        self.binary(&[Op::Add, Op::Sub], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Node> {
+        self.binary(&[Op::Mul, Op::Div], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        if self.eat("!") {
+            let node = self.unary()?;
+            expect_kind(&node, Kind::Bool, "!")?;
+            return Ok(Node::Not(Box::new(node)));
+        }
+        if self.eat("-") {
+            let node = self.unary()?;
+            expect_kind(&node, Kind::Number, "-")?;
+            return Ok(Node::Neg(Box::new(node)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Node> {
+        match self.next()? {
+            Token::Number(value) => Ok(Node::Number(value)),
+            Token::Symbol("(") => {
+                let node = self.or()?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Token::Ident(name) => match name.as_str() {
+                "true" => Ok(Node::Bool(true)),
+                "false" => Ok(Node::Bool(false)),
+                "hour" => Ok(Node::Var(Var::Hour)),
+                "minute" => Ok(Node::Var(Var::Minute)),
+                "weekday" => Ok(Node::Var(Var::Weekday)),
+                "area" => self.area(),
+                _ => bail!("Unknown name {}", name),
+            },
+            token => bail!("Unexpected {}", token),
+        }
+    }
+
+    fn area(&mut self) -> Result<Node> {
+        self.expect("(")?;
+        let source = match self.peek() {
+            Some(Token::Str(source)) => {
+                let source = source.clone();
+                self.pos += 1;
+                self.expect(",")?;
+                Some(source)
+            }
+            _ => None,
+        };
+        let area_code = match self.next()? {
+            Token::Number(code) if code.fract() == 0.0 => code as i32,
+            token => bail!("Expected an area code, found {}", token),
+        };
+        self.expect(")")?;
+        self.expect(".")?;
+        let field = match self.next()? {
+            Token::Ident(name) => Field::parse(&name)
+                .ok_or_else(|| anyhow!("Unknown area field {}, expected free, total, occupied, occupancy, delta or predicted", name))?,
+            token => bail!("Expected an area field, found {}", token),
+        };
+        Ok(Node::Area { source, area_code, field })
+    }
+}
+
+impl Op {
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::And => "&&",
+            Op::Or => "||",
+        }
+    }
+}
+
+/// Builds `left op right`, checking that both sides have the kind `op`
+/// takes.
+fn binary(op: Op, left: Node, right: Node) -> Result<Node> {
+    let operand = match op {
+        Op::And | Op::Or => Kind::Bool,
+        // Conditions may be compared with each other.
+        Op::Eq | Op::Ne => left.kind(),
+        _ => Kind::Number,
+    };
+    expect_kind(&left, operand, op.symbol())?;
+    expect_kind(&right, operand, op.symbol())?;
+    Ok(Node::Binary(op, Box::new(left), Box::new(right)))
+}
+
+fn expect_kind(node: &Node, kind: Kind, op: &str) -> Result<()> {
+    if node.kind() != kind {
+        let expected = match kind {
+            Kind::Number => "a number",
+            Kind::Bool => "a condition",
+        };
+        bail!("{} expects {}", op, expected);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::Sample;
+    use chrono::{TimeZone, Utc};
+    use std::collections::{BTreeMap, HashMap};
+
+    fn latest(source: &str, area_code: i32, free_spaces: i64) -> Latest {
+        Latest {
+            sample: Sample {
+                source: source.to_string(),
+                area_code,
+                location: String::new(),
+                free_spaces,
+                occupancy_pct: None,
+                total_spaces: None,
+                delta_spaces: None,
+                predicted_free_spaces: None,
+                free_spaces_smoothed: None,
+                until_full: None,
+                window: None,
+                data_lag_secs: None,
+                fields: BTreeMap::new(),
+                tags: HashMap::new(),
+                timestamp: Utc.with_ymd_and_hms(2026, 10, 14, 8, 0, 0).unwrap(),
+            },
+            cached: false,
+        }
+    }
+
+    fn eval(text: &str, latest: &[Latest]) -> Result<bool> {
+        let now = chrono_tz::UTC.with_ymd_and_hms(2026, 10, 14, 8, 30, 0).unwrap();
+        Expression::parse(text)?.eval(&Scope { now, latest })
+    }
+
+    fn error(text: &str) -> String {
+        format!("{:#}", Expression::parse(text).unwrap_err())
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert!(eval("true || false && false", &[]).unwrap());
+        assert!(!eval("(true || false) && false", &[]).unwrap());
+        assert!(eval("1 + 2 * 3 == 7", &[]).unwrap());
+    }
+
+    #[test]
+    fn rejects_chained_comparisons() {
+        assert!(error("1 < hour < 3").contains("cannot be chained"));
+        assert!(eval("1 < hour && hour < 9", &[]).unwrap());
+    }
+
+    #[test]
+    fn not_takes_a_condition() {
+        assert!(error("!area(1).free < 5").contains("! expects a condition"));
+        assert!(eval("!(area(1).free < 5)", &[latest("a", 1, 10)]).unwrap());
+    }
+
+    #[test]
+    fn area_with_and_without_source() {
+        let latest = [latest("a", 12, 3), latest("b", 12, 30)];
+        assert!(eval("area(\"a\", 12).free == 3", &latest).unwrap());
+        assert!(eval("area(\"b\", 12).free == 30", &latest).unwrap());
+        assert_eq!(
+            Expression::parse("area(\"a\", 12).free < area(12).free").unwrap().areas(),
+            [(Some("a"), 12), (None, 12)],
+        );
+        let error = eval("area(\"c\", 12).free < 5", &latest).unwrap_err();
+        assert!(error.to_string().contains("no data for area 12 of c"));
+    }
+
+    #[test]
+    fn or_short_circuits_over_missing_areas() {
+        let latest = [latest("a", 1, 3)];
+        assert!(eval("area(1).free < 5 || area(2).free < 5", &latest).unwrap());
+        assert!(eval("area(2).free < 5 || area(1).free < 5", &latest).is_err());
+        assert!(!eval("area(1).free > 5 && area(2).free < 5", &latest).unwrap());
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(error("area(\"a, 1).free < 5").contains("Unterminated string"));
+        assert!(error("hour < 5 )").contains("Unexpected \")\""));
+        assert!(error("hour < 5 hour").contains("Unexpected hour"));
+        assert!(error("hour + 1").contains("not a condition"));
+        assert!(error("hour <").contains("Unexpected end"));
+    }
+}
@@ -0,0 +1,176 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Cleans up one area's values before they are written.
+#[derive(Debug, Deserialize)]
+pub struct FilterConfig {
+    /// Clamps values to between 0 and the area's `total_capacity`, or to 0
+    /// and up without one.
+    #[serde(default)]
+    pub clamp: bool,
+    /// Rejects a value more than this many free spaces away from the last
+    /// accepted one, unless the next scrape confirms the new level.
+    pub max_spike: Option<i64>,
+    /// Adds a `free_spaces_smoothed` field, the exponential moving average
+    /// of the accepted values with this weight for the newest one.
+    pub smoothing_alpha: Option<f64>,
+}
+
+/// An accepted value, after clamping.
+pub struct Filtered {
+    pub free_spaces: i64,
+    pub smoothed: Option<f64>,
+}
+
+#[derive(Default)]
+struct AreaState {
+    last: Option<i64>,
+    /// A rejected spike, accepted if the next value is close to it.
+    held: Option<i64>,
+    /// The previous value sampled since the last accepted one, which
+    /// confirms a spike between two writes the way `held` does.
+    sampled: Option<i64>,
+    smoothed: Option<f64>,
+}
+
+/// Filter state per area of one source.
+#[derive(Default)]
+pub struct Filters {
+    areas: HashMap<i32, AreaState>,
+}
+
+impl Filters {
+    /// Runs `value` through the filters, describing why if it is rejected.
+    pub fn apply(
+        &mut self,
+        config: &FilterConfig,
+        capacity: Option<i64>,
+        area_code: i32,
+        value: i64,
+    ) -> Result<Filtered, String> {
+        let value = clamp(config, capacity, value);
+        let state = self.areas.entry(area_code).or_default();
+        state.sampled = None;
+        if let (Some(max_spike), Some(last)) = (config.max_spike, state.last)
+            && (value - last).abs() > max_spike
+            && state.held.is_none_or(|held| (value - held).abs() > max_spike)
+        {
+            state.held = Some(value);
+            return Err(format!(
+                "changed by {} since the last accepted value ({} -> {}), waiting for the next scrape to confirm",
+                value - last, last, value,
+            ));
+        }
+
+        state.held = None;
+        state.last = Some(value);
+        if let Some(alpha) = config.smoothing_alpha {
+            let smoothed = state.smoothed.map_or(value as f64, |smoothed| alpha * value as f64 + (1.0 - alpha) * smoothed);
+            state.smoothed = Some(smoothed);
+        }
+        Ok(Filtered { free_spaces: value, smoothed: config.smoothing_alpha.and(state.smoothed) })
+    }
+
+    /// Runs a value sampled between two writes through the clamp and spike
+    /// filters, leaving the last accepted value and the average alone.
+    pub fn check_sample(
+        &mut self,
+        config: &FilterConfig,
+        capacity: Option<i64>,
+        area_code: i32,
+        value: i64,
+    ) -> Result<i64, String> {
+        let value = clamp(config, capacity, value);
+        let state = self.areas.entry(area_code).or_default();
+        let previous = state.sampled.replace(value);
+        if let (Some(max_spike), Some(last)) = (config.max_spike, state.last)
+            && (value - last).abs() > max_spike
+            && previous.is_none_or(|previous| (value - previous).abs() > max_spike)
+        {
+            return Err(format!(
+                "changed by {} since the last accepted value ({} -> {})",
+                value - last, last, value,
+            ));
+        }
+        Ok(value)
+    }
+}
+
+fn clamp(config: &FilterConfig, capacity: Option<i64>, value: i64) -> i64 {
+    if config.clamp {
+        value.clamp(0, capacity.unwrap_or(i64::MAX))
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(clamp: bool, max_spike: Option<i64>, smoothing_alpha: Option<f64>) -> FilterConfig {
+        FilterConfig { clamp, max_spike, smoothing_alpha }
+    }
+
+    #[test]
+    fn clamps_to_capacity() {
+        let config = config(true, None, None);
+        let mut filters = Filters::default();
+        assert_eq!(filters.apply(&config, Some(100), 1, 130).unwrap().free_spaces, 100);
+        assert_eq!(filters.apply(&config, Some(100), 1, -4).unwrap().free_spaces, 0);
+        assert_eq!(filters.apply(&config, None, 1, 130).unwrap().free_spaces, 130);
+    }
+
+    #[test]
+    fn rejects_a_spike_until_confirmed() {
+        let config = config(false, Some(20), None);
+        let mut filters = Filters::default();
+        assert_eq!(filters.apply(&config, None, 1, 50).unwrap().free_spaces, 50);
+        // A single spike is rejected and dropped when the next value is back
+        // near the old level.
+        assert!(filters.apply(&config, None, 1, 0).is_err());
+        assert_eq!(filters.apply(&config, None, 1, 48).unwrap().free_spaces, 48);
+        // A lasting change is accepted on the second value.
+        assert!(filters.apply(&config, None, 1, 10).is_err());
+        assert_eq!(filters.apply(&config, None, 1, 12).unwrap().free_spaces, 12);
+        assert_eq!(filters.apply(&config, None, 1, 11).unwrap().free_spaces, 11);
+        // Areas are tracked separately.
+        assert_eq!(filters.apply(&config, None, 2, 90).unwrap().free_spaces, 90);
+    }
+
+    #[test]
+    fn smoothing_seeds_with_the_first_value() {
+        let unsmoothed = config(false, None, None);
+        assert_eq!(Filters::default().apply(&unsmoothed, None, 1, 40).unwrap().smoothed, None);
+
+        let config = config(false, None, Some(0.5));
+        let mut filters = Filters::default();
+        assert_eq!(filters.apply(&config, None, 1, 40).unwrap().smoothed, Some(40.0));
+        assert_eq!(filters.apply(&config, None, 1, 20).unwrap().smoothed, Some(30.0));
+        assert_eq!(filters.apply(&config, None, 1, 30).unwrap().smoothed, Some(30.0));
+    }
+
+    #[test]
+    fn checks_samples_without_moving_the_written_state() {
+        let config = config(true, Some(20), Some(0.5));
+        let mut filters = Filters::default();
+        assert_eq!(filters.apply(&config, Some(100), 1, 50).unwrap().smoothed, Some(50.0));
+        assert_eq!(filters.check_sample(&config, Some(100), 1, 45), Ok(45));
+        // Clamped, then rejected as a spike until the next sample confirms
+        // the new level.
+        assert!(filters.check_sample(&config, Some(100), 1, 150).is_err());
+        assert_eq!(filters.check_sample(&config, Some(100), 1, 140), Ok(100));
+        // The written value is still compared with the last accepted one.
+        assert!(filters.apply(&config, Some(100), 1, 100).is_err());
+        assert_eq!(filters.apply(&config, Some(100), 1, 52).unwrap().smoothed, Some(51.0));
+    }
+
+    #[test]
+    fn rejected_spikes_do_not_move_the_average() {
+        let config = config(false, Some(20), Some(0.5));
+        let mut filters = Filters::default();
+        assert_eq!(filters.apply(&config, None, 1, 40).unwrap().smoothed, Some(40.0));
+        assert!(filters.apply(&config, None, 1, 0).is_err());
+        assert_eq!(filters.apply(&config, None, 1, 44).unwrap().smoothed, Some(42.0));
+    }
+}
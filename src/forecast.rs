@@ -0,0 +1,34 @@
+use chrono::{DateTime, Datelike, TimeDelta, Timelike, Utc};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+
+/// Average free spaces per area and hour of the week, learned from the
+/// values scraped since startup.
+#[derive(Default)]
+pub struct Forecaster {
+    /// Sum and count of the values seen, keyed by area code and hour of the
+    /// week (0 is Monday 00:00-01:00 local time).
+    averages: HashMap<(i32, u32), (f64, u64)>,
+}
+
+fn hour_of_week(at: DateTime<Utc>, timezone: Tz) -> u32 {
+    let local = at.with_timezone(&timezone);
+    local.weekday().num_days_from_monday() * 24 + local.hour()
+}
+
+impl Forecaster {
+    pub fn record(&mut self, area_code: i32, free_spaces: i64, at: DateTime<Utc>, timezone: Tz) {
+        let (sum, count) = self.averages.entry((area_code, hour_of_week(at, timezone))).or_default();
+        *sum += free_spaces as f64;
+        *count += 1;
+    }
+
+    /// Expected free spaces one hour after `at`, once that hour of the week
+    /// has been observed at least once.
+    pub fn predict(&self, area_code: i32, at: DateTime<Utc>, timezone: Tz) -> Option<f64> {
+        let hour = hour_of_week(at + TimeDelta::hours(1), timezone);
+        self.averages
+            .get(&(area_code, hour))
+            .map(|(sum, count)| sum / *count as f64)
+    }
+}
@@ -0,0 +1,307 @@
+use crate::config::AppConfig;
+use crate::http::{self, TlsConfig};
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tracing::info;
+
+/// Where `provision-grafana` pushes the dashboard.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct GrafanaConfig {
+    /// E.g. `https://grafana.example.com`.
+    pub url: String,
+    /// Service account token with permission to write dashboards.
+    pub token: String,
+    /// UID of the Grafana data source pointing at the configured InfluxDB,
+    /// shown in its URL under Connections > Data sources.
+    pub datasource_uid: String,
+    /// UID of the folder to put the dashboard in, the General folder if
+    /// unset.
+    pub folder_uid: Option<String>,
+    /// Provisioning again replaces the dashboard with this UID.
+    #[serde(default = "default_dashboard_uid")]
+    pub dashboard_uid: String,
+    #[serde(default = "default_title")]
+    pub title: String,
+    pub tls: Option<TlsConfig>,
+}
+
+fn default_dashboard_uid() -> String {
+    "msparking".to_string()
+}
+
+fn default_title() -> String {
+    "Parking".to_string()
+}
+
+/// Creates or replaces the dashboard, printing it instead with `dry_run`.
+pub async fn provision(config: &AppConfig, dry_run: bool) -> Result<()> {
+    let grafana = config.grafana.as_ref()
+        .context("provision-grafana needs a [grafana] section")?;
+    let body = json!({
+        "dashboard": dashboard(config, grafana),
+        "folderUid": grafana.folder_uid,
+        "overwrite": true,
+        "message": "Provisioned by msparking",
+    });
+
+    if dry_run {
+        println!("{}", serde_json::to_string_pretty(&body)?);
+        return Ok(());
+    }
+
+    let builder = reqwest::Client::builder().timeout(Duration::from_secs(30));
+    let client = http::with_tls(builder, grafana.tls.as_ref())?
+        .build()
+        .context("Failed to build Grafana HTTP client")?;
+    let response = client.post(format!("{}/api/dashboards/db", grafana.url.trim_end_matches('/')))
+        .bearer_auth(&grafana.token)
+        .json(&body)
+        .send()
+        .await
+        .context("Failed to reach Grafana")?;
+
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        bail!("Grafana rejected the dashboard with {}: {}", status, text);
+    }
+    let url = serde_json::from_str::<Value>(&text).ok()
+        .and_then(|reply| reply["url"].as_str().map(str::to_string))
+        .unwrap_or_default();
+    info!("Provisioned dashboard {:?} at {}{}", grafana.title, grafana.url.trim_end_matches('/'), url);
+    Ok(())
+}
+
+fn dashboard(config: &AppConfig, grafana: &GrafanaConfig) -> Value {
+    let query = Query::new(config);
+    let datasource = json!({ "type": "influxdb", "uid": grafana.datasource_uid });
+    let area_name = format!("${{__field.labels.{}}}", config.influxdb.schema.name("location"));
+
+    // Occupancy needs a capacity; without any, free spaces are the next best
+    // thing to plot over time.
+    let any_capacity = config.areas.values()
+        .chain(config.sources().flat_map(|source| source.areas.values()))
+        .any(|area| area.total_capacity.is_some());
+    let (title, field, unit) = if any_capacity {
+        ("Occupancy", "occupancy_pct", "percent")
+    } else {
+        ("Free spaces over time", "free_spaces", "none")
+    };
+
+    let mut panels = vec![
+        json!({
+            "id": 1,
+            "type": "stat",
+            "title": "Current availability",
+            "datasource": datasource,
+            "gridPos": { "x": 0, "y": 0, "w": 24, "h": 5 },
+            "targets": [query.over_time("free_spaces")],
+            "options": {
+                "reduceOptions": { "calcs": ["lastNotNull"], "fields": "", "values": false },
+                "colorMode": "background",
+                "textMode": "value_and_name",
+            },
+            "fieldConfig": {
+                "defaults": {
+                    "unit": "none",
+                    "displayName": area_name,
+                    "thresholds": {
+                        "mode": "absolute",
+                        "steps": [
+                            { "color": "red", "value": null },
+                            { "color": "orange", "value": 10 },
+                            { "color": "green", "value": 50 },
+                        ],
+                    },
+                },
+                "overrides": [],
+            },
+        }),
+        json!({
+            "id": 2,
+            "type": "timeseries",
+            "title": title,
+            "datasource": datasource,
+            "gridPos": { "x": 0, "y": 5, "w": 24, "h": 10 },
+            "targets": [query.over_time(field)],
+            "fieldConfig": { "defaults": { "unit": unit, "displayName": area_name }, "overrides": [] },
+        }),
+    ];
+
+    if config.influxdb.scraper_health {
+        panels.push(json!({
+            "id": 3,
+            "type": "timeseries",
+            "title": "Scraper health",
+            "datasource": datasource,
+            "gridPos": { "x": 0, "y": 15, "w": 24, "h": 8 },
+            "targets": [query.health("A", "fetch_latency_ms"), query.health("B", "consecutive_failures")],
+            "fieldConfig": { "defaults": {}, "overrides": [] },
+        }));
+    } else {
+        info!("influxdb.scraper_health is off, leaving out the scraper health panel");
+    }
+
+    let mut annotations = Vec::new();
+    if config.influxdb.events {
+        let mut annotation = query.events();
+        annotation["name"] = "Maintenance and outages".into();
+        annotation["datasource"] = datasource.clone();
+        annotation["enable"] = true.into();
+        annotation["iconColor"] = "rgba(255, 152, 48, 0.4)".into();
+        annotations.push(annotation);
+    }
+
+    json!({
+        "uid": grafana.dashboard_uid,
+        "title": grafana.title,
+        "tags": ["msparking"],
+        "timezone": config.timezone.name(),
+        "refresh": "1m",
+        "time": { "from": "now-24h", "to": "now" },
+        "schemaVersion": 39,
+        "templating": { "list": [location_variable(config)] },
+        "annotations": { "list": annotations },
+        "panels": panels,
+    })
+}
+
+/// A multi-value `location` variable listing every configured area name.
+fn location_variable(config: &AppConfig) -> Value {
+    let mut locations = BTreeSet::new();
+    for source in config.sources() {
+        for area_code in config.areas.keys().chain(source.areas.keys()) {
+            locations.insert(source.location_for(&config.areas, *area_code));
+        }
+    }
+    let options: Vec<Value> = locations.iter()
+        .map(|location| json!({ "text": location, "value": location, "selected": false }))
+        .collect();
+    let query = locations.iter()
+        .map(|location| location.replace(',', "\\,"))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    json!({
+        "type": "custom",
+        "name": "location",
+        "label": "Area",
+        "query": query,
+        "options": options,
+        "multi": true,
+        "includeAll": true,
+        "current": { "text": "All", "value": "$__all" },
+    })
+}
+
+/// Builds queries in Flux for InfluxDB 2.x and InfluxQL for 1.x, with the
+/// configured bucket or database and schema names.
+struct Query<'a> {
+    config: &'a AppConfig,
+}
+
+impl<'a> Query<'a> {
+    fn new(config: &'a AppConfig) -> Self {
+        Query { config }
+    }
+
+    /// The mean of `field` per area and Grafana interval, for the selected
+    /// locations.
+    fn over_time(&self, field: &str) -> Value {
+        let schema = &self.config.influxdb.schema;
+        let location = schema.name("location");
+        let field = schema.name(field);
+
+        if self.config.influxdb.version == 1 {
+            return json!({
+                "refId": "A",
+                "rawQuery": true,
+                "resultFormat": "time_series",
+                "query": format!(
+                    "SELECT mean({}) FROM {} WHERE $timeFilter AND {} =~ /^${{location:regex}}$/ GROUP BY time($__interval), {} fill(none)",
+                    quote(field), self.from(&schema.measurement), quote(location), quote(location),
+                ),
+            });
+        }
+
+        json!({
+            "refId": "A",
+            "query": format!(
+                "from(bucket: {})\n  |> range(start: v.timeRangeStart, stop: v.timeRangeStop)\n  |> filter(fn: (r) => r._measurement == {} and r._field == {})\n  |> filter(fn: (r) => contains(value: r[{}], set: ${{location:json}}))\n  |> group(columns: [{}])\n  |> aggregateWindow(every: v.windowPeriod, fn: mean, createEmpty: false)",
+                quote(&self.config.influxdb.bucket),
+                quote(&schema.measurement),
+                quote(field),
+                quote(location),
+                quote(location),
+            ),
+        })
+    }
+
+    /// One `scraper_health` field per source.
+    fn health(&self, ref_id: &str, field: &str) -> Value {
+        if self.config.influxdb.version == 1 {
+            return json!({
+                "refId": ref_id,
+                "rawQuery": true,
+                "resultFormat": "time_series",
+                "alias": format!("$tag_source {}", field),
+                "query": format!(
+                    "SELECT mean({}) FROM {} WHERE $timeFilter GROUP BY time($__interval), \"source\" fill(none)",
+                    quote(field), self.from("scraper_health"),
+                ),
+            });
+        }
+
+        json!({
+            "refId": ref_id,
+            "query": format!(
+                "from(bucket: {})\n  |> range(start: v.timeRangeStart, stop: v.timeRangeStop)\n  |> filter(fn: (r) => r._measurement == \"scraper_health\" and r._field == {})\n  |> group(columns: [\"source\", \"_field\"])\n  |> aggregateWindow(every: v.windowPeriod, fn: mean, createEmpty: false)",
+                quote(&self.config.influxdb.bucket),
+                quote(field),
+            ),
+        })
+    }
+
+    /// Finished `parking_events` as regions from their start to their end.
+    /// InfluxQL cannot compute the start, so 1.x shows the end of each
+    /// event instead.
+    fn events(&self) -> Value {
+        if self.config.influxdb.version == 1 {
+            return json!({
+                "query": format!(
+                    "SELECT \"text\", \"duration_secs\" FROM {} WHERE $timeFilter AND \"active\" = false",
+                    self.from("parking_events"),
+                ),
+                "textColumn": "text",
+                "tagsColumn": "event",
+            });
+        }
+
+        json!({
+            "target": {
+                "refId": "Anno",
+                "query": format!(
+                    "from(bucket: {})\n  |> range(start: v.timeRangeStart, stop: v.timeRangeStop)\n  |> filter(fn: (r) => r._measurement == \"parking_events\" and r._field == \"duration_secs\")\n  |> map(fn: (r) => ({{_time: time(v: int(v: r._time) - int(v: r._value * 1000000000.0)), timeEnd: r._time, text: r.event + \" on \" + r.source}}))",
+                    quote(&self.config.influxdb.bucket),
+                ),
+            },
+        })
+    }
+
+    /// The measurement qualified with the 1.x retention policy, if any.
+    fn from(&self, measurement: &str) -> String {
+        match &self.config.influxdb.retention_policy {
+            Some(policy) => format!("{}.{}", quote(policy), quote(measurement)),
+            None => quote(measurement),
+        }
+    }
+}
+
+/// A double-quoted Flux string or InfluxQL identifier, which escape the
+/// same way.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
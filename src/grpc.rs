@@ -0,0 +1,86 @@
+use crate::live::Live;
+use crate::sink::Sample;
+use anyhow::{Context, Result};
+use proto::availability_server::{Availability, AvailabilityServer};
+use std::pin::Pin;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tokio_util::sync::CancellationToken;
+use tonic::{Request, Response, Status};
+use tracing::{error, info, warn};
+
+pub mod proto {
+    tonic::include_proto!("msparking.v1");
+}
+
+impl proto::SubscribeRequest {
+    fn matches(&self, sample: &Sample) -> bool {
+        (self.sources.is_empty() || self.sources.contains(&sample.source))
+            && (self.area_codes.is_empty() || self.area_codes.contains(&sample.area_code))
+    }
+}
+
+impl From<Sample> for proto::Sample {
+    fn from(sample: Sample) -> Self {
+        proto::Sample {
+            source: sample.source,
+            area_code: sample.area_code,
+            location: sample.location,
+            free_spaces: sample.free_spaces,
+            occupancy_pct: sample.occupancy_pct,
+            delta_spaces: sample.delta_spaces,
+            tags: sample.tags,
+            timestamp_ms: sample.timestamp.timestamp_millis(),
+        }
+    }
+}
+
+struct AvailabilityService {
+    live: Live,
+}
+
+type SampleStream = Pin<Box<dyn Stream<Item = Result<proto::Sample, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Availability for AvailabilityService {
+    type SubscribeAvailabilityStream = SampleStream;
+
+    async fn subscribe_availability(
+        &self,
+        request: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<SampleStream>, Status> {
+        let filter = request.into_inner();
+        let stream = BroadcastStream::new(self.live.subscribe()).filter_map(move |item| match item {
+            Ok(sample) if filter.matches(&sample) => Some(Ok(sample.into())),
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                warn!("gRPC subscriber fell behind, skipped {} samples", skipped);
+                None
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Binds the gRPC server and serves it in the background.
+pub async fn spawn(listen: &str, live: Live, shutdown: CancellationToken) -> Result<()> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("Failed to bind gRPC server to {}", listen))?;
+
+    info!("gRPC server listening on {}", listen);
+
+    let service = AvailabilityServer::new(AvailabilityService { live });
+    tokio::spawn(async move {
+        let server = tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown.cancelled_owned());
+        if let Err(e) = server.await {
+            error!("gRPC server stopped: {}", e);
+        }
+    });
+
+    Ok(())
+}
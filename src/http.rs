@@ -0,0 +1,69 @@
+//! Settings shared by the HTTP clients for sources and InfluxDB.
+
+use anyhow::{Context, Result, anyhow};
+use reqwest::{Certificate, ClientBuilder, Identity, Proxy};
+use serde::Deserialize;
+use tracing::warn;
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct TlsConfig {
+    /// PEM file with additional root certificates to trust, e.g. an
+    /// internal CA.
+    pub ca_cert: Option<String>,
+    /// Skips certificate validation entirely. Only meant for labs.
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+    /// PEM client certificate presented to servers that require mutual
+    /// TLS, together with `client_key`.
+    pub client_cert: Option<String>,
+    /// PEM private key for `client_cert`, in PKCS#8 format.
+    pub client_key: Option<String>,
+}
+
+/// Routes every request through `proxy_url` if set. Credentials can be
+/// embedded in the URL; they are kept out of error messages.
+pub fn with_proxy(builder: ClientBuilder, proxy_url: Option<&str>) -> Result<ClientBuilder> {
+    let Some(url) = proxy_url else {
+        return Ok(builder);
+    };
+
+    let proxy = Proxy::all(url).context("Invalid proxy URL")?;
+    Ok(builder.proxy(proxy))
+}
+
+pub fn with_tls(mut builder: ClientBuilder, tls: Option<&TlsConfig>) -> Result<ClientBuilder> {
+    let Some(tls) = tls else {
+        return Ok(builder);
+    };
+
+    if let Some(path) = &tls.ca_cert {
+        let pem = std::fs::read(path)
+            .with_context(|| format!("Failed to read CA certificate {}", path))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("Invalid CA certificate {}", path))?;
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    if tls.accept_invalid_certs {
+        warn!("TLS certificate validation is disabled");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    match (&tls.client_cert, &tls.client_key) {
+        (Some(cert), Some(key)) => {
+            let cert_pem = std::fs::read(cert)
+                .with_context(|| format!("Failed to read client certificate {}", cert))?;
+            let key_pem = std::fs::read(key)
+                .with_context(|| format!("Failed to read client key {}", key))?;
+            let identity = Identity::from_pkcs8_pem(&cert_pem, &key_pem)
+                .with_context(|| format!("Invalid client certificate {} or key {}", cert, key))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => return Err(anyhow!("tls.client_cert and tls.client_key must be set together")),
+    }
+
+    Ok(builder)
+}
@@ -0,0 +1,81 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::time;
+use tracing::info;
+
+/// Lets only one of several replicas scrape, the others stand by until it
+/// goes away. Leadership is an exclusive lock on a file on storage shared by
+/// all replicas, which the OS releases when the holder exits or its host
+/// drops off (NFS needs v4 or a working lock daemon).
+#[derive(Debug, Deserialize)]
+pub struct LeaderConfig {
+    pub lock_file: PathBuf,
+    /// How often a standby tries to take over.
+    #[serde(default = "default_retry_secs")]
+    pub retry_secs: u64,
+}
+
+fn default_retry_secs() -> u64 {
+    5
+}
+
+/// Held for as long as this instance leads; dropping it hands leadership
+/// to a standby.
+pub struct Leadership {
+    _file: File,
+}
+
+/// Waits until this instance holds the lock, or returns `None` if `stop`
+/// completes first.
+pub async fn acquire(config: &LeaderConfig, stop: impl Future<Output = ()>) -> Result<Option<Leadership>> {
+    tokio::pin!(stop);
+    let mut announced = false;
+
+    loop {
+        if let Some(leadership) = try_acquire(config)? {
+            info!("Acquired leadership through {}", config.lock_file.display());
+            return Ok(Some(leadership));
+        }
+        if !announced {
+            info!("Another instance holds {}, standing by", config.lock_file.display());
+            announced = true;
+        }
+
+        // Standing by is healthy, keep systemd from restarting us.
+        #[cfg(unix)]
+        crate::systemd::cycle_finished(true);
+
+        tokio::select! {
+            _ = &mut stop => return Ok(None),
+            _ = time::sleep(Duration::from_secs(config.retry_secs)) => {}
+        }
+    }
+}
+
+fn try_acquire(config: &LeaderConfig) -> Result<Option<Leadership>> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&config.lock_file)
+        .with_context(|| format!("Failed to open lock file {}", config.lock_file.display()))?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => return Ok(None),
+        Err(TryLockError::Error(e)) => {
+            return Err(e).with_context(|| format!("Failed to lock {}", config.lock_file.display()));
+        }
+    }
+
+    // Only informative, for whoever wonders which replica leads.
+    file.set_len(0)
+        .and_then(|()| writeln!(file, "{}", std::process::id()))
+        .with_context(|| format!("Failed to write lock file {}", config.lock_file.display()))?;
+
+    Ok(Some(Leadership { _file: file }))
+}
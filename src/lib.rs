@@ -0,0 +1,36 @@
+//! Scrapes parking space availability from upstream APIs and writes it to
+//! InfluxDB and other sinks. The `msparking` binary is a thin CLI over
+//! [`scheduler`]; the modules are public so other tools can embed the
+//! scraper or reuse individual pieces.
+
+pub mod alerts;
+pub mod anomaly;
+pub mod archive;
+pub mod cache;
+pub mod config;
+pub mod dedup;
+pub mod events;
+pub mod expr;
+pub mod filter;
+pub mod forecast;
+pub mod grafana;
+pub mod grpc;
+pub mod http;
+pub mod leader;
+pub mod live;
+pub mod logging;
+pub mod metrics;
+pub mod notifiers;
+pub mod rollup;
+pub mod s3;
+pub mod sampling;
+pub mod scheduler;
+pub mod server;
+#[cfg(windows)]
+pub mod service;
+pub mod sink;
+pub mod source;
+#[cfg(unix)]
+pub mod systemd;
+pub mod trend;
+pub mod vault;
@@ -0,0 +1,121 @@
+use crate::sink::Sample;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde_json::json;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Samples buffered per subscriber before a slow one starts missing some.
+const CAPACITY: usize = 1024;
+
+/// How far back `/api/v1/history` goes.
+const HISTORY: TimeDelta = TimeDelta::hours(1);
+
+/// Fans out every scraped sample to streaming clients: gRPC, WebSocket and
+/// SSE subscribers. Also keeps the most recent sample per area for
+/// `/api/v1/latest` and the last hour of values for `/api/v1/history`.
+/// Cheap to clone; all clones share the same state.
+#[derive(Clone)]
+pub struct Live {
+    tx: broadcast::Sender<Sample>,
+    state: Arc<Mutex<State>>,
+}
+
+/// Source name and area code.
+type AreaKey = (String, i32);
+
+#[derive(Default)]
+struct State {
+    latest: BTreeMap<AreaKey, Latest>,
+    history: BTreeMap<AreaKey, VecDeque<(DateTime<Utc>, i64)>>,
+}
+
+#[derive(Clone)]
+pub struct Latest {
+    pub sample: Sample,
+    /// Written from the cache rather than freshly scraped.
+    pub cached: bool,
+}
+
+pub struct History {
+    pub source: String,
+    pub area_code: i32,
+    /// Timestamp and free spaces, oldest first.
+    pub values: Vec<(DateTime<Utc>, i64)>,
+}
+
+impl Default for Live {
+    fn default() -> Self {
+        Live {
+            tx: broadcast::channel(CAPACITY).0,
+            state: Arc::default(),
+        }
+    }
+}
+
+impl Live {
+    pub fn publish(&self, samples: &[Sample], cached: bool) {
+        let mut state = self.state.lock().unwrap();
+        let cutoff = Utc::now() - HISTORY;
+        for sample in samples {
+            let key = (sample.source.clone(), sample.area_code);
+            let history = state.history.entry(key.clone()).or_default();
+            history.push_back((sample.timestamp, sample.free_spaces));
+            while history.front().is_some_and(|(at, _)| *at < cutoff) {
+                history.pop_front();
+            }
+            state.latest.insert(key, Latest { sample: sample.clone(), cached });
+        }
+        drop(state);
+
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+        for sample in samples {
+            let _ = self.tx.send(sample.clone());
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Sample> {
+        self.tx.subscribe()
+    }
+
+    /// The most recent sample of every area, ordered by source and area.
+    pub fn latest(&self) -> Vec<Latest> {
+        self.state.lock().unwrap().latest.values().cloned().collect()
+    }
+
+    /// Free spaces per area over the last hour.
+    pub fn history(&self) -> Vec<History> {
+        let cutoff = Utc::now() - HISTORY;
+        self.state.lock().unwrap()
+            .history
+            .iter()
+            .map(|((source, area_code), values)| History {
+                source: source.clone(),
+                area_code: *area_code,
+                values: values.iter().filter(|(at, _)| *at >= cutoff).copied().collect(),
+            })
+            .collect()
+    }
+}
+
+/// The JSON form of a sample sent to WebSocket and SSE clients.
+pub fn to_json(sample: &Sample) -> serde_json::Value {
+    json!({
+        "source": sample.source,
+        "area_code": sample.area_code,
+        "location": sample.location,
+        "free_spaces": sample.free_spaces,
+        "occupancy_pct": sample.occupancy_pct,
+        "delta_spaces": sample.delta_spaces,
+        "seconds_until_full": sample.until_full.map(|until_full| until_full.secs),
+        "until_full_valid": sample.until_full.map(|until_full| until_full.valid),
+        "full_at": sample.until_full
+            .filter(|until_full| until_full.valid)
+            .and_then(|until_full| TimeDelta::try_milliseconds((until_full.secs * 1000.0) as i64))
+            .map(|until| (sample.timestamp + until).to_rfc3339()),
+        "tags": sample.tags,
+        "timestamp": sample.timestamp.to_rfc3339(),
+    })
+}
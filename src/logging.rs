@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use serde::Deserialize;
+use tracing::Level;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+#[derive(Debug, Deserialize, Default)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Exports scrape cycles as traces to an OpenTelemetry collector.
+    pub otlp: Option<OtlpConfig>,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per event, with event fields such as `area_code` or
+    /// `duration_ms` at the top level and the enclosing spans alongside.
+    Json,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OtlpConfig {
+    /// OTLP/HTTP traces endpoint, e.g. `http://localhost:4318/v1/traces`.
+    pub endpoint: String,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+}
+
+fn default_service_name() -> String {
+    "msparking".to_string()
+}
+
+/// Flushes pending spans to the collector when dropped.
+pub struct Guard {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if let Some(provider) = &self.provider
+            && let Err(e) = provider.shutdown()
+        {
+            eprintln!("Failed to flush traces: {}", e);
+        }
+    }
+}
+
+/// Installs the global subscriber. `filter` takes the same directives as
+/// `RUST_LOG` and overrides it; only errors are logged if neither is set.
+/// Events from crates still using `log` are captured as well. Traces are
+/// exported independently of the log filter, for this crate's spans only.
+/// With `event_log`, this crate's info events and everything at warning or
+/// above also go to the Windows event log; the flag is ignored elsewhere.
+pub fn init(config: &LoggingConfig, filter: Option<&str>, event_log: bool) -> Result<Guard> {
+    let builder = EnvFilter::builder().with_default_directive(LevelFilter::ERROR.into());
+    let filter = match filter {
+        Some(filter) => builder.parse(filter)
+            .with_context(|| format!("Invalid log filter {}", filter))?,
+        None => builder.from_env_lossy(),
+    };
+
+    let fmt = tracing_subscriber::fmt::layer();
+    let fmt = match config.format {
+        LogFormat::Text => fmt.with_filter(filter).boxed(),
+        LogFormat::Json => fmt.json().flatten_event(true).with_filter(filter).boxed(),
+    };
+
+    let provider = config.otlp.as_ref().map(build_tracer_provider).transpose()?;
+    let otlp = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer("msparking"))
+            .with_filter(Targets::new().with_target("msparking", Level::INFO))
+    });
+
+    #[cfg(windows)]
+    let event_log = event_log
+        .then(|| tracing_layer_win_eventlog::EventLogLayer::new("msparking"))
+        .transpose()
+        .context("Failed to open the event log")?
+        .map(|layer| {
+            layer.with_filter(Targets::new().with_target("msparking", Level::INFO).with_default(Level::WARN))
+        });
+    #[cfg(not(windows))]
+    let event_log = {
+        // There is no event log outside Windows.
+        let _ = event_log;
+        None::<tracing_subscriber::layer::Identity>
+    };
+
+    tracing_subscriber::registry()
+        .with(fmt)
+        .with(otlp)
+        .with(event_log)
+        .try_init()
+        .context("Failed to install logger")?;
+
+    Ok(Guard { provider })
+}
+
+fn build_tracer_provider(config: &OtlpConfig) -> Result<SdkTracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.endpoint)
+        .build()
+        .context("Failed to build OTLP exporter")?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(config.service_name.clone()).build())
+        .build())
+}
@@ -1,189 +1,169 @@
-use anyhow::{Context, Result};
-use chrono::{DateTime, Timelike, Utc};
-use chrono_tz::Asia::Shanghai;
-use config::{Config, File};
-use futures::stream;
-use influxdb2::Client;
-use influxdb2::models::DataPoint;
-use log::{error, info};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::time;
+use anyhow::Result;
+use clap::{Parser, Subcommand, ValueEnum};
+use msparking::{config, grafana, logging, scheduler};
+use std::process::ExitCode;
+use tracing::{error, info};
 
-#[derive(Debug, Deserialize)]
-struct AppConfig {
-    api: ApiConfig,
-    influxdb: InfluxDbConfig,
-}
-
-#[derive(Debug, Deserialize)]
-struct ApiConfig {
-    url: String,
-    scraping_interval_secs: u64,
-}
+#[cfg(windows)]
+use msparking::service;
 
-#[derive(Debug, Deserialize)]
-struct InfluxDbConfig {
-    url: String,
-    org: String,
-    bucket: String,
-    token: String,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-struct ApiResponse {
-    success: bool,
-    #[serde(rename = "msparkingData")]
-    msparking_data: Vec<AreaData>,
-    date: String,
+#[derive(Debug, Parser)]
+#[command(version, about = "Scrapes parking space availability into InfluxDB")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    
+    /// Path to the configuration file (extension optional)
+    #[arg(long, global = true, default_value = "config/default")]
+    config: String,
+    
+    /// Log filter, e.g. `info` or `msparking=debug` (overrides RUST_LOG)
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+    
+    /// Run a single scrape cycle and exit with 0 if every source succeeded,
+    /// 2 if any source failed, or 1 on a startup error
+    #[arg(long)]
+    once: bool,
+    
+    /// Print points as line protocol instead of writing them to InfluxDB
+    #[arg(long, global = true)]
+    dry_run: bool,
+    
+    /// Check that InfluxDB accepts writes and fetch once from every source,
+    /// then exit
+    #[arg(long, conflicts_with = "once")]
+    check: bool,
+    
+    /// Install or uninstall the Windows service running this executable
+    /// with the given config, or run as that service (only meant to be
+    /// started by the service manager)
+    #[arg(long, value_name = "ACTION", conflicts_with_all = ["once", "check"])]
+    service: Option<ServiceAction>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-struct AreaData {
-    #[serde(rename = "areaCode")]
-    area_code: i32,
-    #[serde(rename = "areaFreeSpaceNum")]
-    area_free_space_num: i64,
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Create or update a dashboard with occupancy over time, current
+    /// availability and scraper health in the Grafana set up under
+    /// [grafana], then exit. With --dry-run the dashboard is printed instead
+    ProvisionGrafana,
+    /// Push samples stored by the SQLite sink to InfluxDB, then exit
+    Export,
+    /// Write the saved API responses (*.json, or *.gz from the archive) in
+    /// DIR and its subdirectories to InfluxDB as historical points, then
+    /// exit
+    Backfill {
+        /// Directory of saved responses, e.g. `data/archive/default`
+        dir: String,
+        /// Source whose area mappings and tags apply, the first configured
+        /// source by default
+        #[arg(long)]
+        source: Option<String>,
+    },
 }
 
-async fn load_config() -> Result<AppConfig> {
-    let config = Config::builder()
-        .add_source(File::with_name("config/default"))
-        .build()
-        .context("Failed to load configuration")?;
-    
-    config.try_deserialize::<AppConfig>()
-        .context("Failed to deserialize configuration")
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum ServiceAction {
+    Install,
+    Uninstall,
+    Run,
 }
 
-async fn fetch_parking_data(url: &str) -> Result<ApiResponse> {
-    let response = reqwest::get(url)
-        .await
-        .context("Failed to send request")?;
+#[tokio::main]
+async fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
     
-    let data = response.json::<ApiResponse>()
-        .await
-        .context("Failed to parse API response")?;
+    match cli.service {
+        Some(ServiceAction::Install) => {
+            service::install(&cli.config, cli.log_level.as_deref())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(ServiceAction::Uninstall) => {
+            service::uninstall()?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        // Services start in the system directory.
+        Some(ServiceAction::Run) => service::enter_install_dir()?,
+        None => {}
+    }
+    let as_service = cli.service == Some(ServiceAction::Run);
     
-    Ok(data)
-}
-
-fn is_in_maintenance_window() -> bool {
-    let shanghai_time: DateTime<chrono_tz::Tz> = Utc::now().with_timezone(&Shanghai);
-    let hour = shanghai_time.hour();
-    let minute = shanghai_time.minute();
+    let config = config::load_config(&cli.config).await?;
+    let _logging = logging::init(&config.logging, cli.log_level.as_deref(), as_service)?;
+    info!("Configuration loaded successfully");
     
-    (hour == 23 && minute >= 50) || (hour == 0 && minute < 20)
-}
-
-fn create_data_point(area: &AreaData) -> DataPoint {
-    let now = Utc::now();
-    
-    DataPoint::builder("parking_spaces")
-        .tag("area_code", area.area_code.to_string())
-        .tag("location", match area.area_code {
-            12 => "SIP-B25-B26".to_string(),
-            2 => "ZHONGMENG".to_string(),
-            _ => "Unknown".to_string(),
-        })
-        .field("free_spaces", area.area_free_space_num)
-        .timestamp(now.timestamp_nanos_opt().unwrap())
-        .build()
-        .unwrap()
-}
-
-async fn run_scraper(config: AppConfig) -> Result<()> {
-    let client = Arc::new(Client::new(&config.influxdb.url, &config.influxdb.org, &config.influxdb.token));
-    
-    let mut interval = time::interval(Duration::from_secs(config.api.scraping_interval_secs));
-    
-    let mut cached_data: HashMap<i32, AreaData> = HashMap::new();
-    
-    info!("Starting parking data scraper. Interval: {} seconds", config.api.scraping_interval_secs);
-    
-    loop {
-        interval.tick().await;
-        info!("Fetching parking data...");
-        
-        let using_cache = is_in_maintenance_window();
-        if using_cache {
-            info!("Currently in maintenance window (23:50-00:20 GMT+8), using cached data");
-            
-            if cached_data.is_empty() {
-                info!("No cached data available, attempting to fetch fresh data anyway");
-            } else {
-                let data_points: Vec<DataPoint> = cached_data.values()
-                    .map(|area| create_data_point(area))
-                    .collect();
-                
-                info!("Using cached data for {} areas", data_points.len());
-                
-                for area in cached_data.values() {
-                    info!("Cached - Area {}: {} free spaces", area.area_code, area.area_free_space_num);
-                }
-                
-                match client.write(&config.influxdb.bucket, stream::iter(data_points))
-                    .await {
-                        Ok(_) => info!("Successfully wrote cached data to InfluxDB"),
-                        Err(e) => error!("Failed to write cached data to InfluxDB: {}", e),
-                    }
-                
-                continue;
+    let dry_run = cli.dry_run || config.dry_run;
+    if dry_run {
+        info!("Dry run, points are printed as line protocol instead of being written");
+    }
+    
+    match cli.command {
+        Some(Command::ProvisionGrafana) => {
+            grafana::provision(&config, dry_run).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Export) => {
+            scheduler::run_export(config, dry_run).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Backfill { dir, source }) => {
+            scheduler::run_backfill(config, &dir, source.as_deref(), dry_run).await?;
+            return Ok(ExitCode::SUCCESS);
         }
+        None => {}
     }
-        
-        match fetch_parking_data(&config.api.url).await {
-            Ok(data) => {
-                if !data.success {
-                    error!("API returned unsuccessful response");
-                    continue;
-                }
-                
-                if data.msparking_data.is_empty() {
-                    error!("No parking data available in the response");
-                    continue;
-                }
-                
-                for area in &data.msparking_data {
-                    if area.area_free_space_num > 0 {
-                        cached_data.insert(area.area_code, area.clone());
-                    }
-                }
-                
-                let data_points: Vec<DataPoint> = data.msparking_data
-                    .iter()
-                    .map(create_data_point)
-                    .collect();
-                
-                info!("Found parking data for {} areas", data_points.len());
-                
-                for area in &data.msparking_data {
-                    info!("Area {}: {} free spaces", area.area_code, area.area_free_space_num);
-                }
-                
-                match client.write(&config.influxdb.bucket, stream::iter(data_points))
-                    .await {
-                        Ok(_) => info!("Successfully wrote data to InfluxDB"),
-                        Err(e) => error!("Failed to write to InfluxDB: {}", e),
-                    }
-            }
-            Err(e) => {
-                error!("Error fetching parking data: {}", e);
+    
+    if cli.check {
+        scheduler::run_check(&config, dry_run).await?;
+        info!("All checks passed");
+        return Ok(ExitCode::SUCCESS);
+    }
+    
+    if cli.once {
+        return match scheduler::run_once(config, dry_run).await {
+            Ok(()) => Ok(ExitCode::SUCCESS),
+            Err(e) if e.is::<scheduler::SourcesFailed>() => {
+                error!("{:#}", e);
+                Ok(ExitCode::from(2))
             }
-        }
+            Err(e) => Err(e),
+        };
     }
+    
+    if as_service {
+        service::run(config, &cli.config)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    
+    scheduler::run_scraper(config, &cli.config, dry_run).await?;
+    
+    Ok(ExitCode::SUCCESS)
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
+/// Stand-in for `msparking::service` on other platforms.
+#[cfg(not(windows))]
+mod service {
+    use anyhow::{Result, bail};
+    use msparking::config::AppConfig;
     
-    let config = load_config().await?;
-    info!("Configuration loaded successfully");
+    pub fn install(_config_path: &str, _log_level: Option<&str>) -> Result<()> {
+        unavailable()
+    }
     
-    run_scraper(config).await?;
+    pub fn uninstall() -> Result<()> {
+        unavailable()
+    }
     
-    Ok(())
-}
\ No newline at end of file
+    pub fn enter_install_dir() -> Result<()> {
+        unavailable()
+    }
+    
+    pub fn run(_config: AppConfig, _config_path: &str) -> Result<()> {
+        unavailable()
+    }
+    
+    fn unavailable() -> Result<()> {
+        bail!("--service is only available on Windows")
+    }
+}
@@ -0,0 +1,252 @@
+use crate::source::CircuitState;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// In-process registry of scraper metrics, rendered in the Prometheus text
+/// exposition format by the `/metrics` endpoint.
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    free_spaces: BTreeMap<(String, i32), AreaGauge>,
+    sources: BTreeMap<String, SourceStats>,
+    sinks: BTreeMap<String, SinkStats>,
+    influxdb_up: Option<bool>,
+    influxdb_last_write: Option<DateTime<Utc>>,
+    write_queue: Option<QueueStats>,
+}
+
+#[derive(Default)]
+struct QueueStats {
+    depth: usize,
+    dropped: u64,
+}
+
+#[derive(Default)]
+struct SinkStats {
+    samples: u64,
+    errors: u64,
+    dropped: u64,
+}
+
+struct AreaGauge {
+    location: String,
+    value: i64,
+}
+
+type RenderStat = fn(&SourceStats) -> String;
+type RenderSinkStat = fn(&SinkStats) -> u64;
+
+#[derive(Default)]
+struct SourceStats {
+    cycles: u64,
+    fetch_errors: u64,
+    write_errors: u64,
+    rate_limited: u64,
+    fetch_seconds_sum: f64,
+    fetch_count: u64,
+    last_success: Option<DateTime<Utc>>,
+    consecutive_failures: u32,
+    circuit_state: u8,
+}
+
+impl Metrics {
+    fn with_source<F: FnOnce(&mut SourceStats)>(&self, source: &str, f: F) {
+        let mut inner = self.inner.lock().unwrap();
+        f(inner.sources.entry(source.to_string()).or_default());
+    }
+
+    pub fn record_cycle(&self, source: &str, success: bool) {
+        self.with_source(source, |stats| {
+            stats.cycles += 1;
+            if success {
+                stats.last_success = Some(Utc::now());
+                stats.consecutive_failures = 0;
+            } else {
+                stats.consecutive_failures += 1;
+            }
+        });
+    }
+
+    pub fn record_fetch(&self, source: &str, duration: Duration, success: bool) {
+        self.with_source(source, |stats| {
+            stats.fetch_seconds_sum += duration.as_secs_f64();
+            stats.fetch_count += 1;
+            if !success {
+                stats.fetch_errors += 1;
+            }
+        });
+    }
+
+    pub fn record_write_error(&self, source: &str) {
+        self.with_source(source, |stats| stats.write_errors += 1);
+    }
+
+    pub fn record_rate_limited(&self, source: &str) {
+        self.with_source(source, |stats| stats.rate_limited += 1);
+    }
+
+    pub fn set_circuit_state(&self, source: &str, state: CircuitState) {
+        self.with_source(source, |stats| stats.circuit_state = state.as_gauge());
+    }
+
+    /// Records the outcome of the most recent write attempt to InfluxDB.
+    pub fn record_influxdb_write(&self, success: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.influxdb_up = Some(success);
+        if success {
+            inner.influxdb_last_write = Some(Utc::now());
+        }
+    }
+
+    pub fn set_write_queue_depth(&self, depth: usize) {
+        self.inner.lock().unwrap().write_queue.get_or_insert_default().depth = depth;
+    }
+
+    pub fn record_write_queue_dropped(&self) {
+        self.inner.lock().unwrap().write_queue.get_or_insert_default().dropped += 1;
+    }
+
+    pub fn record_sink_write(&self, sink: &str, samples: usize, success: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        let stats = inner.sinks.entry(sink.to_string()).or_default();
+        if success {
+            stats.samples += samples as u64;
+        } else {
+            stats.errors += 1;
+        }
+    }
+
+    pub fn record_sink_dropped(&self, sink: &str, samples: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.sinks.entry(sink.to_string()).or_default().dropped += samples as u64;
+    }
+
+    /// `None` until the first write has been attempted.
+    pub fn influxdb_up(&self) -> Option<bool> {
+        self.inner.lock().unwrap().influxdb_up
+    }
+
+    /// When InfluxDB last accepted a write, `None` if it never has.
+    pub fn influxdb_last_write(&self) -> Option<DateTime<Utc>> {
+        self.inner.lock().unwrap().influxdb_last_write
+    }
+
+    pub fn last_success(&self, source: &str) -> Option<DateTime<Utc>> {
+        self.inner.lock().unwrap()
+            .sources
+            .get(source)
+            .and_then(|stats| stats.last_success)
+    }
+
+    pub fn consecutive_failures(&self, source: &str) -> u32 {
+        self.inner.lock().unwrap()
+            .sources
+            .get(source)
+            .map_or(0, |stats| stats.consecutive_failures)
+    }
+
+    pub fn set_free_spaces(&self, source: &str, area_code: i32, location: &str, value: i64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.free_spaces.insert(
+            (source.to_string(), area_code),
+            AreaGauge { location: location.to_string(), value },
+        );
+    }
+
+    pub fn render(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP parking_free_spaces Latest number of free parking spaces per area.\n");
+        out.push_str("# TYPE parking_free_spaces gauge\n");
+        for ((source, area_code), gauge) in &inner.free_spaces {
+            let _ = writeln!(
+                out,
+                "parking_free_spaces{{source=\"{}\",area_code=\"{}\",location=\"{}\"}} {}",
+                escape(source), area_code, escape(&gauge.location), gauge.value,
+            );
+        }
+
+        let counters: [(&str, &str, &str, RenderStat); 6] = [
+            ("msparking_cycles_total", "counter", "Scrape cycles run.", |s| s.cycles.to_string()),
+            ("msparking_fetch_errors_total", "counter", "Failed API fetches.", |s| s.fetch_errors.to_string()),
+            ("msparking_write_errors_total", "counter", "Failed InfluxDB writes.", |s| s.write_errors.to_string()),
+            ("msparking_rate_limited_total", "counter", "Fetches rejected by the API's rate limit.", |s| {
+                s.rate_limited.to_string()
+            }),
+            ("msparking_circuit_state", "gauge", "Circuit breaker state: 0 closed, 1 open, 2 half-open.", |s| {
+                s.circuit_state.to_string()
+            }),
+            ("msparking_last_success_timestamp_seconds", "gauge", "Unix time of the last successful cycle.", |s| {
+                s.last_success.map(|t| t.timestamp()).unwrap_or(0).to_string()
+            }),
+        ];
+
+        for (name, kind, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (source, stats) in &inner.sources {
+                let _ = writeln!(out, "{}{{source=\"{}\"}} {}", name, escape(source), value(stats));
+            }
+        }
+
+        if let Some(up) = inner.influxdb_up {
+            out.push_str("# HELP msparking_influxdb_up Whether the last InfluxDB write succeeded.\n");
+            out.push_str("# TYPE msparking_influxdb_up gauge\n");
+            let _ = writeln!(out, "msparking_influxdb_up {}", up as u8);
+        }
+
+        if let Some(at) = inner.influxdb_last_write {
+            out.push_str("# HELP msparking_influxdb_last_write_timestamp_seconds Unix time of the last successful InfluxDB write.\n");
+            out.push_str("# TYPE msparking_influxdb_last_write_timestamp_seconds gauge\n");
+            let _ = writeln!(out, "msparking_influxdb_last_write_timestamp_seconds {}", at.timestamp());
+        }
+
+        if let Some(queue) = &inner.write_queue {
+            out.push_str("# HELP msparking_write_queue_depth Batches waiting for the writer.\n");
+            out.push_str("# TYPE msparking_write_queue_depth gauge\n");
+            let _ = writeln!(out, "msparking_write_queue_depth {}", queue.depth);
+            out.push_str("# HELP msparking_write_queue_dropped_total Batches dropped because the write queue was full.\n");
+            out.push_str("# TYPE msparking_write_queue_dropped_total counter\n");
+            let _ = writeln!(out, "msparking_write_queue_dropped_total {}", queue.dropped);
+        }
+
+        let sink_counters: [(&str, &str, RenderSinkStat); 3] = [
+            ("msparking_sink_samples_total", "Samples written per sink.", |s| s.samples),
+            ("msparking_sink_errors_total", "Failed writes per sink.", |s| s.errors),
+            ("msparking_sink_dropped_total", "Samples dropped because a sink fell behind.", |s| s.dropped),
+        ];
+
+        for (name, help, value) in sink_counters {
+            if inner.sinks.is_empty() {
+                break;
+            }
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (sink, stats) in &inner.sinks {
+                let _ = writeln!(out, "{}{{sink=\"{}\"}} {}", name, escape(sink), value(stats));
+            }
+        }
+
+        out.push_str("# HELP msparking_fetch_duration_seconds Time spent fetching from the API.\n");
+        out.push_str("# TYPE msparking_fetch_duration_seconds summary\n");
+        for (source, stats) in &inner.sources {
+            let source = escape(source);
+            let _ = writeln!(out, "msparking_fetch_duration_seconds_sum{{source=\"{}\"}} {}", source, stats.fetch_seconds_sum);
+            let _ = writeln!(out, "msparking_fetch_duration_seconds_count{{source=\"{}\"}} {}", source, stats.fetch_count);
+        }
+
+        out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
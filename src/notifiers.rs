@@ -0,0 +1,761 @@
+use crate::config::MaintenanceWindow;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use base64::prelude::{BASE64_STANDARD, Engine};
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use hmac::{Hmac, Mac};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use tracing::{error, info, warn};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A single alert, independent of the channel it is delivered through.
+#[derive(Clone)]
+pub struct Notification {
+    pub title: String,
+    pub message: String,
+    /// Extra values available to webhook templates as `{name}` placeholders.
+    pub vars: Vec<(&'static str, String)>,
+    pub priority: Priority,
+}
+
+/// Channels that support it deliver high-priority notifications more
+/// intrusively: ntfy as urgent, Bark as time-sensitive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Priority {
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, notification: &Notification) -> Result<()>;
+}
+
+/// A named entry under `[notifiers]`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct NotifierConfig {
+    #[serde(flatten)]
+    pub kind: NotifierKind,
+    pub quiet_hours: Option<QuietHours>,
+}
+
+/// The channel, selected by the `type` key.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierKind {
+    Webhook(WebhookConfig),
+    Telegram(TelegramConfig),
+    Slack(ChatWebhookConfig),
+    Discord(ChatWebhookConfig),
+    Email(EmailConfig),
+    Ntfy(NtfyConfig),
+    WeCom(WeComConfig),
+    DingTalk(DingTalkConfig),
+    Bark(BarkConfig),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// JSON body with `{title}`, `{message}` and alert-specific placeholders
+    /// such as `{area_code}` or `{free_spaces}`.
+    #[serde(default = "default_webhook_template")]
+    pub template: String,
+}
+
+fn default_webhook_template() -> String {
+    r#"{"title": "{title}", "text": "{message}"}"#.to_string()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    #[serde(deserialize_with = "string_or_integer")]
+    pub chat_id: String,
+    #[serde(default = "default_telegram_api_url")]
+    pub api_url: String,
+}
+
+fn default_telegram_api_url() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+/// Chat ids are numeric but often written as strings; accept both.
+fn string_or_integer<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        String(String),
+        Integer(i64),
+    }
+
+    Ok(match Id::deserialize(deserializer)? {
+        Id::String(id) => id,
+        Id::Integer(id) => id.to_string(),
+    })
+}
+
+/// Incoming-webhook URL for Slack or Discord.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatWebhookConfig {
+    pub webhook_url: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    /// Defaults to the usual port for `tls`.
+    pub smtp_port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    #[default]
+    Starttls,
+    Tls,
+    None,
+}
+
+impl SmtpTls {
+    fn default_port(self) -> u16 {
+        match self {
+            SmtpTls::Starttls => 587,
+            SmtpTls::Tls => 465,
+            SmtpTls::None => 25,
+        }
+    }
+}
+
+/// Push notifications through an ntfy server, ntfy.sh by default. Access
+/// controlled topics take either an access `token` or a username and
+/// password.
+#[derive(Debug, Deserialize, Clone)]
+pub struct NtfyConfig {
+    #[serde(default = "default_ntfy_server")]
+    pub server: String,
+    pub topic: String,
+    pub token: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// 1 (min) to 5 (max); the server's default of 3 if unset.
+    pub priority: Option<u8>,
+    /// Tags or emoji shortcodes shown with the notification.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+/// Native iOS push notifications through a Bark server, the public
+/// api.day.app by default.
+#[derive(Debug, Deserialize, Clone)]
+pub struct BarkConfig {
+    #[serde(default = "default_bark_server")]
+    pub server: String,
+    /// The key shown in the Bark app.
+    pub device_key: String,
+    /// Groups notifications in the notification center.
+    pub group: Option<String>,
+    /// One of Bark's ringtones, e.g. `alarm`.
+    pub sound: Option<String>,
+    /// `active`, `timeSensitive` (shown during Focus), `passive` or
+    /// `critical`.
+    pub level: Option<String>,
+    /// Opened when the notification is tapped.
+    pub url: Option<String>,
+}
+
+fn default_bark_server() -> String {
+    "https://api.day.app".to_string()
+}
+
+/// A WeCom (企业微信) group robot, by the webhook URL including its `key`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WeComConfig {
+    pub webhook_url: String,
+    /// Phone numbers of members to @-mention.
+    #[serde(default)]
+    pub mention_mobiles: Vec<String>,
+    #[serde(default)]
+    pub mention_all: bool,
+}
+
+/// A DingTalk group robot, by the webhook URL including its
+/// `access_token`. Robots with the "sign" security setting also need their
+/// `secret`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DingTalkConfig {
+    pub webhook_url: String,
+    pub secret: Option<String>,
+    /// Phone numbers of members to @-mention.
+    #[serde(default)]
+    pub mention_mobiles: Vec<String>,
+    #[serde(default)]
+    pub mention_all: bool,
+}
+
+/// A daily window, in the top-level `timezone` unless overridden, during
+/// which the notifier stays silent. `start` is inclusive and `end`
+/// exclusive; windows may wrap past midnight and an empty `days` list means
+/// every day.
+#[derive(Debug, Deserialize, Clone)]
+pub struct QuietHours {
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub timezone: Option<Tz>,
+    #[serde(default)]
+    pub mode: QuietMode,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuietMode {
+    /// Drops alerts raised during quiet hours.
+    Suppress,
+    /// Holds them back and sends a single digest once quiet hours end.
+    #[default]
+    Digest,
+}
+
+impl QuietHours {
+    pub fn contains(&self, now: DateTime<Utc>, default_tz: Tz) -> bool {
+        let local = now.with_timezone(&self.timezone.unwrap_or(default_tz));
+        let window = MaintenanceWindow {
+            days: self.days.clone(),
+            start: self.start,
+            end: self.end,
+        };
+        window.contains(local.weekday(), local.time())
+    }
+}
+
+impl NotifierKind {
+    pub fn build(&self, client: &reqwest::Client) -> Box<dyn Notifier> {
+        match self {
+            NotifierKind::Webhook(config) => Box::new(WebhookNotifier {
+                client: client.clone(),
+                config: config.clone(),
+            }),
+            NotifierKind::Telegram(config) => Box::new(TelegramNotifier {
+                client: client.clone(),
+                config: config.clone(),
+            }),
+            NotifierKind::Slack(config) => Box::new(SlackNotifier {
+                client: client.clone(),
+                webhook_url: config.webhook_url.clone(),
+            }),
+            NotifierKind::Discord(config) => Box::new(DiscordNotifier {
+                client: client.clone(),
+                webhook_url: config.webhook_url.clone(),
+            }),
+            NotifierKind::Email(config) => Box::new(EmailNotifier {
+                config: config.clone(),
+            }),
+            NotifierKind::Ntfy(config) => Box::new(NtfyNotifier {
+                client: client.clone(),
+                config: config.clone(),
+            }),
+            NotifierKind::WeCom(config) => Box::new(WeComNotifier {
+                client: client.clone(),
+                config: config.clone(),
+            }),
+            NotifierKind::DingTalk(config) => Box::new(DingTalkNotifier {
+                client: client.clone(),
+                config: config.clone(),
+            }),
+            NotifierKind::Bark(config) => Box::new(BarkNotifier {
+                client: client.clone(),
+                config: config.clone(),
+            }),
+        }
+    }
+}
+
+/// Notifications held back during quiet hours, per notifier name. A
+/// notifier has an entry only while its digest task is waiting.
+static DIGESTS: Mutex<BTreeMap<String, Vec<Notification>>> = Mutex::new(BTreeMap::new());
+
+/// How often a waiting digest checks whether quiet hours are over.
+const DIGEST_CHECK: Duration = Duration::from_secs(60);
+
+/// Sends the notification through the named notifiers, or through every
+/// configured notifier if `names` is `None`. Delivery happens in the
+/// background so a slow channel never delays the scrape loop. Notifiers in
+/// their quiet hours drop the notification or add it to their digest.
+pub fn dispatch(
+    notifiers: &HashMap<String, NotifierConfig>,
+    names: Option<&[String]>,
+    client: &reqwest::Client,
+    timezone: Tz,
+    notification: Notification,
+) {
+    let configured: Vec<(&String, &NotifierConfig)> = match names {
+        Some(names) => names.iter()
+            .filter_map(|name| match notifiers.get_key_value(name) {
+                Some(entry) => Some(entry),
+                None => {
+                    warn!("Alert references unknown notifier {}", name);
+                    None
+                }
+            })
+            .collect(),
+        None => notifiers.iter().collect(),
+    };
+
+    let now = Utc::now();
+    let mut selected: Vec<(String, Box<dyn Notifier>)> = Vec::new();
+    for (name, config) in configured {
+        let Some(quiet) = config.quiet_hours.as_ref().filter(|quiet| quiet.contains(now, timezone)) else {
+            selected.push((name.clone(), config.kind.build(client)));
+            continue;
+        };
+        match quiet.mode {
+            QuietMode::Suppress => info!("Quiet hours for {}, alert dropped: {}", name, notification.title),
+            QuietMode::Digest => hold(name, config, client, timezone, &notification),
+        }
+    }
+
+    if selected.is_empty() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        for (name, notifier) in selected {
+            if let Err(e) = notifier.send(&notification).await {
+                error!("Failed to deliver alert via {}: {:#}", name, e);
+            }
+        }
+    });
+}
+
+/// Adds the notification to the notifier's digest, starting the task that
+/// sends it once quiet hours are over if this is the first one.
+fn hold(name: &str, config: &NotifierConfig, client: &reqwest::Client, timezone: Tz, notification: &Notification) {
+    info!("Quiet hours for {}, alert added to the digest: {}", name, notification.title);
+
+    let mut digests = DIGESTS.lock().unwrap();
+    if let Some(held) = digests.get_mut(name) {
+        held.push(notification.clone());
+        return;
+    }
+    digests.insert(name.to_string(), vec![notification.clone()]);
+
+    let name = name.to_string();
+    let notifier = config.kind.build(client);
+    let quiet = config.quiet_hours.clone().unwrap();
+    tokio::spawn(async move {
+        while quiet.contains(Utc::now(), timezone) {
+            tokio::time::sleep(DIGEST_CHECK).await;
+        }
+
+        let held = DIGESTS.lock().unwrap().remove(&name).unwrap_or_default();
+        info!("Quiet hours for {} are over, sending a digest of {} alerts", name, held.len());
+        if let Err(e) = notifier.send(&digest(&held)).await {
+            error!("Failed to deliver alert digest via {}: {:#}", name, e);
+        }
+    });
+}
+
+/// Combines the notifications held back during quiet hours into one.
+fn digest(held: &[Notification]) -> Notification {
+    let message = held.iter()
+        .map(|notification| format!("{}\n{}", notification.title, notification.message))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    Notification {
+        title: format!("{} alerts during quiet hours", held.len()),
+        message,
+        vars: vec![("count", held.len().to_string())],
+        priority: held.iter().map(|notification| notification.priority).max().unwrap_or_default(),
+    }
+}
+
+/// Substitutes `{title}`, `{message}`, `{priority}` and the notification's
+/// vars in `template`, passing every value through `escape` first.
+pub fn render(template: &str, notification: &Notification, escape: fn(&str) -> String) -> String {
+    let mut out = template
+        .replace("{title}", &escape(&notification.title))
+        .replace("{message}", &escape(&notification.message))
+        .replace("{priority}", notification.priority.as_str());
+    for (name, value) in &notification.vars {
+        out = out.replace(&format!("{{{}}}", name), &escape(value));
+    }
+    out
+}
+
+/// Escapes a value so it can sit inside a quoted JSON string.
+pub fn json_escape(value: &str) -> String {
+    let quoted = serde_json::Value::from(value).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+async fn post_json(client: &reqwest::Client, url: &str, body: &serde_json::Value) -> Result<()> {
+    // Incoming-webhook URLs are secret, so keep them out of errors.
+    client.post(url)
+        .json(body)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| anyhow::anyhow!("{}", e.without_url()))?;
+    Ok(())
+}
+
+struct WebhookNotifier {
+    client: reqwest::Client,
+    config: WebhookConfig,
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        self.client.post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(render(&self.config.template, notification, json_escape))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| anyhow::anyhow!("Webhook request failed: {}", e.without_url()))?;
+        Ok(())
+    }
+}
+
+struct TelegramNotifier {
+    client: reqwest::Client,
+    config: TelegramConfig,
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let url = format!("{}/bot{}/sendMessage", self.config.api_url, self.config.bot_token);
+        let body = serde_json::json!({
+            "chat_id": self.config.chat_id,
+            "text": format!("{}\n{}", notification.title, notification.message),
+        });
+
+        // The URL contains the bot token, so keep it out of error messages.
+        self.client.post(url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| anyhow::anyhow!("Telegram sendMessage failed: {}", e.without_url()))?;
+        Ok(())
+    }
+}
+
+struct SlackNotifier {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let body = serde_json::json!({
+            "text": format!("*{}*\n{}", notification.title, notification.message),
+        });
+        post_json(&self.client, &self.webhook_url, &body).await.context("Slack webhook request failed")
+    }
+}
+
+struct DiscordNotifier {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+#[async_trait]
+impl Notifier for DiscordNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let body = serde_json::json!({
+            "content": format!("**{}**\n{}", notification.title, notification.message),
+        });
+        post_json(&self.client, &self.webhook_url, &body).await.context("Discord webhook request failed")
+    }
+}
+
+struct NtfyNotifier {
+    client: reqwest::Client,
+    config: NtfyConfig,
+}
+
+#[async_trait]
+impl Notifier for NtfyNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        // JSON publishing keeps non-ASCII titles intact, unlike the Title
+        // header.
+        let mut body = serde_json::json!({
+            "topic": self.config.topic,
+            "title": notification.title,
+            "message": notification.message,
+        });
+        let priority = match notification.priority {
+            Priority::High => Some(5),
+            Priority::Normal => self.config.priority,
+        };
+        if let Some(priority) = priority {
+            body["priority"] = priority.into();
+        }
+        if !self.config.tags.is_empty() {
+            body["tags"] = self.config.tags.clone().into();
+        }
+
+        let mut request = self.client.post(self.config.server.trim_end_matches('/')).json(&body);
+        if let Some(token) = &self.config.token {
+            request = request.bearer_auth(token);
+        } else if let Some(username) = &self.config.username {
+            request = request.basic_auth(username, self.config.password.as_ref());
+        }
+
+        request.send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("Publishing to ntfy topic {} failed", self.config.topic))?;
+        Ok(())
+    }
+}
+
+struct BarkNotifier {
+    client: reqwest::Client,
+    config: BarkConfig,
+}
+
+#[async_trait]
+impl Notifier for BarkNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let mut body = serde_json::json!({
+            "device_key": self.config.device_key,
+            "title": notification.title,
+            "body": notification.message,
+        });
+        // A configured `critical` level stays, it is already the loudest.
+        let level = match notification.priority {
+            Priority::High if self.config.level.as_deref() != Some("critical") => Some("timeSensitive".to_string()),
+            _ => self.config.level.clone(),
+        };
+        let options = [
+            ("group", &self.config.group),
+            ("sound", &self.config.sound),
+            ("level", &level),
+            ("url", &self.config.url),
+        ];
+        for (name, value) in options {
+            if let Some(value) = value {
+                body[name] = value.clone().into();
+            }
+        }
+
+        let url = format!("{}/push", self.config.server.trim_end_matches('/'));
+        let response: serde_json::Value = self.client.post(&url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("POST to {} failed", url))?
+            .json()
+            .await
+            .context("Failed to parse the Bark response")?;
+
+        match response["code"].as_i64() {
+            Some(200) => Ok(()),
+            _ => anyhow::bail!("Bark rejected the push: {}", response["message"].as_str().unwrap_or("")),
+        }
+    }
+}
+
+/// Posts to a WeCom or DingTalk robot, which answer 200 even when they
+/// reject a message and report the outcome in `errcode` instead.
+async fn post_robot(client: &reqwest::Client, url: reqwest::Url, body: &serde_json::Value) -> Result<()> {
+    // The URL holds the robot's key or token, so keep it out of errors.
+    let response: serde_json::Value = client.post(url)
+        .json(body)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| anyhow::anyhow!("{}", e.without_url()))?
+        .json()
+        .await
+        .context("Failed to parse the robot's response")?;
+
+    match response["errcode"].as_i64() {
+        Some(0) => Ok(()),
+        code => anyhow::bail!(
+            "Robot rejected the message with errcode {}: {}",
+            code.map_or("none".to_string(), |code| code.to_string()),
+            response["errmsg"].as_str().unwrap_or(""),
+        ),
+    }
+}
+
+struct WeComNotifier {
+    client: reqwest::Client,
+    config: WeComConfig,
+}
+
+#[async_trait]
+impl Notifier for WeComNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let mut mentions = self.config.mention_mobiles.clone();
+        if self.config.mention_all {
+            mentions.push("@all".to_string());
+        }
+        let body = serde_json::json!({
+            "msgtype": "text",
+            "text": {
+                "content": format!("{}\n{}", notification.title, notification.message),
+                "mentioned_mobile_list": mentions,
+            },
+        });
+
+        let url = reqwest::Url::parse(&self.config.webhook_url).context("Invalid WeCom webhook URL")?;
+        post_robot(&self.client, url, &body).await.context("WeCom robot request failed")
+    }
+}
+
+struct DingTalkNotifier {
+    client: reqwest::Client,
+    config: DingTalkConfig,
+}
+
+#[async_trait]
+impl Notifier for DingTalkNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        // Mentions only work if the numbers also appear in the text.
+        let mut content = format!("{}\n{}", notification.title, notification.message);
+        for mobile in &self.config.mention_mobiles {
+            content.push_str(&format!(" @{}", mobile));
+        }
+        let body = serde_json::json!({
+            "msgtype": "text",
+            "text": { "content": content },
+            "at": {
+                "atMobiles": self.config.mention_mobiles,
+                "isAtAll": self.config.mention_all,
+            },
+        });
+
+        let url = dingtalk_url(&self.config.webhook_url, self.config.secret.as_deref(), Utc::now())?;
+        post_robot(&self.client, url, &body).await.context("DingTalk robot request failed")
+    }
+}
+
+/// The webhook URL, signed for `now` if the robot has a `secret`.
+fn dingtalk_url(webhook_url: &str, secret: Option<&str>, now: DateTime<Utc>) -> Result<reqwest::Url> {
+    let mut url = reqwest::Url::parse(webhook_url).context("Invalid DingTalk webhook URL")?;
+    if let Some(secret) = secret {
+        let timestamp = now.timestamp_millis().to_string();
+        url.query_pairs_mut()
+            .append_pair("timestamp", &timestamp)
+            .append_pair("sign", &dingtalk_sign(secret, &timestamp));
+    }
+    Ok(url)
+}
+
+/// Signature for DingTalk robots with the "sign" security setting: the
+/// base64 HMAC-SHA256 of the timestamp and secret, keyed with the secret.
+fn dingtalk_sign(secret: &str, timestamp: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}\n{}", timestamp, secret).as_bytes());
+    BASE64_STANDARD.encode(mac.finalize().into_bytes())
+}
+
+struct EmailNotifier {
+    config: EmailConfig,
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let from: Mailbox = self.config.from.parse()
+            .with_context(|| format!("Invalid sender address: {}", self.config.from))?;
+
+        let mut builder = Message::builder()
+            .from(from)
+            .subject(format!("[msparking] {}", notification.title));
+        for to in &self.config.to {
+            let to: Mailbox = to.parse()
+                .with_context(|| format!("Invalid recipient address: {}", to))?;
+            builder = builder.to(to);
+        }
+
+        let message = builder.body(notification.message.clone())
+            .context("Failed to build email")?;
+
+        self.mailer()?
+            .send(message)
+            .await
+            .context("SMTP delivery failed")?;
+        Ok(())
+    }
+}
+
+impl EmailNotifier {
+    fn mailer(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let host = self.config.smtp_host.as_str();
+        let mut builder = match self.config.tls {
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .context("Failed to configure SMTP STARTTLS")?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                .context("Failed to configure SMTP TLS")?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+
+        builder = builder.port(self.config.smtp_port.unwrap_or(self.config.tls.default_port()));
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(builder.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // Computed as in DingTalk's Python example for signed robots.
+    #[test]
+    fn signs_dingtalk_webhooks() {
+        let secret = "SEC000000000000000000000";
+        assert_eq!(dingtalk_sign(secret, "1760430600000"), "Z9jNlcGYWI5gra9XfkDxSMU87Ht3bht9KacrDHl0JcI=");
+
+        let now = Utc.timestamp_millis_opt(1760430600000).unwrap();
+        let url = dingtalk_url("https://oapi.dingtalk.com/robot/send?access_token=abc", Some(secret), now).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://oapi.dingtalk.com/robot/send?access_token=abc&timestamp=1760430600000\
+             &sign=Z9jNlcGYWI5gra9XfkDxSMU87Ht3bht9KacrDHl0JcI%3D",
+        );
+        let url = dingtalk_url("https://oapi.dingtalk.com/robot/send?access_token=abc", None, now).unwrap();
+        assert_eq!(url.as_str(), "https://oapi.dingtalk.com/robot/send?access_token=abc");
+    }
+}
@@ -0,0 +1,177 @@
+use crate::sink::Sample;
+use anyhow::{Context, Result};
+use chrono::{DateTime, DurationRound, NaiveDate, NaiveTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use influxdb2::models::DataPoint;
+use std::collections::{BTreeMap, HashMap};
+
+/// Min, average and max free spaces per area over each hour, built from
+/// the samples of one source.
+#[derive(Default)]
+pub struct Rollup {
+    hour: Option<DateTime<Utc>>,
+    areas: BTreeMap<i32, Aggregate>,
+}
+
+struct Aggregate {
+    source: String,
+    location: String,
+    tags: HashMap<String, String>,
+    min: i64,
+    max: i64,
+    sum: i64,
+    count: i64,
+}
+
+impl Rollup {
+    /// Adds the samples to the current hour. Once a sample from a later
+    /// hour arrives, returns the `parking_spaces_hourly` points of the hour
+    /// that just ended.
+    pub fn add(&mut self, samples: &[Sample]) -> Result<Vec<DataPoint>> {
+        let mut finished = Vec::new();
+
+        for sample in samples {
+            let hour = sample.timestamp.duration_trunc(TimeDelta::hours(1))
+                .context("Failed to truncate sample timestamp")?;
+            if self.hour.is_some_and(|current| current < hour) {
+                finished.extend(self.flush()?);
+            }
+            self.hour = Some(self.hour.map_or(hour, |current| current.max(hour)));
+
+            let aggregate = self.areas.entry(sample.area_code).or_insert_with(|| Aggregate {
+                source: sample.source.clone(),
+                location: sample.location.clone(),
+                tags: sample.tags.clone(),
+                min: sample.free_spaces,
+                max: sample.free_spaces,
+                sum: 0,
+                count: 0,
+            });
+            aggregate.min = aggregate.min.min(sample.free_spaces);
+            aggregate.max = aggregate.max.max(sample.free_spaces);
+            aggregate.sum += sample.free_spaces;
+            aggregate.count += 1;
+        }
+
+        Ok(finished)
+    }
+
+    fn flush(&mut self) -> Result<Vec<DataPoint>> {
+        let Some(hour) = self.hour else {
+            return Ok(Vec::new());
+        };
+
+        std::mem::take(&mut self.areas)
+            .into_iter()
+            .map(|(area_code, aggregate)| {
+                let mut builder = DataPoint::builder("parking_spaces_hourly")
+                    .tag("source", aggregate.source)
+                    .tag("area_code", area_code.to_string())
+                    .tag("location", aggregate.location);
+                for (key, value) in aggregate.tags {
+                    builder = builder.tag(key, value);
+                }
+
+                builder
+                    .field("min_free_spaces", aggregate.min)
+                    .field("avg_free_spaces", aggregate.sum as f64 / aggregate.count as f64)
+                    .field("max_free_spaces", aggregate.max)
+                    .field("samples", aggregate.count)
+                    .timestamp(hour.timestamp_nanos_opt().unwrap())
+                    .build()
+                    .context("Failed to build hourly rollup point")
+            })
+            .collect()
+    }
+}
+
+/// Per-area statistics for each calendar day in the local timezone.
+#[derive(Default)]
+pub struct DailyStats {
+    day: Option<NaiveDate>,
+    areas: BTreeMap<i32, Day>,
+}
+
+struct Day {
+    source: String,
+    location: String,
+    tags: HashMap<String, String>,
+    min: i64,
+    min_at: DateTime<Utc>,
+    /// When the area first reported no free spaces that day.
+    first_full_at: Option<DateTime<Utc>>,
+    count: i64,
+}
+
+impl DailyStats {
+    /// Adds the samples to the current day. Once a sample from a later day
+    /// arrives, returns the `parking_daily_stats` points of the day that
+    /// just ended.
+    pub fn add(&mut self, samples: &[Sample], timezone: Tz) -> Result<Vec<DataPoint>> {
+        let mut finished = Vec::new();
+
+        for sample in samples {
+            let day = sample.timestamp.with_timezone(&timezone).date_naive();
+            if self.day.is_some_and(|current| current < day) {
+                finished.extend(self.flush(timezone)?);
+            }
+            self.day = Some(self.day.map_or(day, |current| current.max(day)));
+
+            let stats = self.areas.entry(sample.area_code).or_insert_with(|| Day {
+                source: sample.source.clone(),
+                location: sample.location.clone(),
+                tags: sample.tags.clone(),
+                min: sample.free_spaces,
+                min_at: sample.timestamp,
+                first_full_at: None,
+                count: 0,
+            });
+            if sample.free_spaces < stats.min {
+                stats.min = sample.free_spaces;
+                stats.min_at = sample.timestamp;
+            }
+            if sample.free_spaces <= 0 && stats.first_full_at.is_none() {
+                stats.first_full_at = Some(sample.timestamp);
+            }
+            stats.count += 1;
+        }
+
+        Ok(finished)
+    }
+
+    /// Points are timestamped with local midnight; times are written as
+    /// local RFC 3339 strings.
+    fn flush(&mut self, timezone: Tz) -> Result<Vec<DataPoint>> {
+        let Some(day) = self.day else {
+            return Ok(Vec::new());
+        };
+        let midnight = timezone.from_local_datetime(&day.and_time(NaiveTime::MIN))
+            .earliest()
+            .map(|at| at.with_timezone(&Utc))
+            .context("Local midnight does not exist")?;
+
+        std::mem::take(&mut self.areas)
+            .into_iter()
+            .map(|(area_code, stats)| {
+                let mut builder = DataPoint::builder("parking_daily_stats")
+                    .tag("source", stats.source)
+                    .tag("area_code", area_code.to_string())
+                    .tag("location", stats.location);
+                for (key, value) in stats.tags {
+                    builder = builder.tag(key, value);
+                }
+                if let Some(at) = stats.first_full_at {
+                    builder = builder.field("first_full_at", at.with_timezone(&timezone).to_rfc3339());
+                }
+
+                builder
+                    .field("min_free_spaces", stats.min)
+                    .field("min_at", stats.min_at.with_timezone(&timezone).to_rfc3339())
+                    .field("samples", stats.count)
+                    .timestamp(midnight.timestamp_nanos_opt().unwrap())
+                    .build()
+                    .context("Failed to build daily stats point")
+            })
+            .collect()
+    }
+}
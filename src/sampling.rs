@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+/// The spread of the values fetched for one area since its last write.
+#[derive(Debug, Clone, Copy)]
+pub struct WindowStats {
+    pub min: i64,
+    pub max: i64,
+    pub mean: f64,
+}
+
+#[derive(Default)]
+struct Window {
+    min: i64,
+    max: i64,
+    sum: i64,
+    count: u32,
+}
+
+/// Values sampled between writes, per area of one source, for
+/// `samples_per_interval`.
+#[derive(Default)]
+pub struct Windows {
+    areas: HashMap<i32, Window>,
+}
+
+impl Windows {
+    pub fn record(&mut self, area_code: i32, value: i64) {
+        let window = self.areas.entry(area_code).or_default();
+        if window.count == 0 {
+            window.min = value;
+            window.max = value;
+        } else {
+            window.min = window.min.min(value);
+            window.max = window.max.max(value);
+        }
+        window.sum += value;
+        window.count += 1;
+    }
+
+    /// Records the written `value` and returns the stats of the window it
+    /// closes, starting a new one.
+    pub fn take(&mut self, area_code: i32, value: i64) -> WindowStats {
+        self.record(area_code, value);
+        let window = self.areas.remove(&area_code).unwrap_or_default();
+        WindowStats {
+            min: window.min,
+            max: window.max,
+            mean: window.sum as f64 / window.count as f64,
+        }
+    }
+
+    /// Drops the windows of areas that were not written.
+    pub fn clear(&mut self) {
+        self.areas.clear();
+    }
+}
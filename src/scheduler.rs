@@ -0,0 +1,1743 @@
+use crate::alerts::{self, Alert, Alerter};
+use crate::archive::Archive;
+use crate::anomaly::{AnomalyAction, Detector};
+use crate::cache::{CacheFile, CachedArea};
+use crate::config::{self, AppConfig, CachePolicy, RetryConfig, Schedule, SourceConfig};
+use crate::dedup::{Dedup, DedupConfig};
+use crate::events::{Event, Events, Gaps};
+use crate::filter::Filters;
+use crate::forecast::Forecaster;
+use crate::grpc;
+use crate::leader;
+use crate::live::Live;
+use crate::metrics::Metrics;
+use crate::rollup::{DailyStats, Rollup};
+use crate::sampling::Windows;
+use crate::server;
+use crate::sink::{self, InfluxSink, Sample, Sink, SqliteSink, Writer};
+use crate::source::{self, AreaData, CircuitBreaker, CircuitState, Fetched, Source};
+#[cfg(unix)]
+use crate::systemd;
+use crate::trend::Recent;
+use crate::vault;
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use influxdb2::models::DataPoint;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, debug, error, field, info, info_span, warn};
+
+/// Watches the directory containing the config file and signals `tx` on
+/// every change to it. The directory is watched rather than the file itself
+/// so that editors which save by renaming a temp file are picked up too.
+fn watch_config(path: &str, tx: mpsc::UnboundedSender<()>) -> Result<RecommendedWatcher> {
+    let path = Path::new(path);
+    let dir = path.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let stem = path.file_stem().map(|s| s.to_os_string());
+    
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            let relevant = event.kind.is_modify() || event.kind.is_create();
+            if relevant && event.paths.iter().any(|p| p.file_stem() == stem.as_deref()) {
+                let _ = tx.send(());
+            }
+        }
+    })
+    .context("Failed to create config watcher")?;
+    
+    watcher.watch(dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", dir.display()))?;
+    
+    Ok(watcher)
+}
+
+/// Signals `tx` on every SIGHUP until shutdown, the usual way of asking a
+/// daemon to reload its configuration.
+#[cfg(unix)]
+async fn reload_signal(tx: mpsc::UnboundedSender<()>, shutdown: CancellationToken) {
+    use tokio::signal::unix::{SignalKind, signal};
+    
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!("Failed to install SIGHUP handler, reloading on SIGHUP is unavailable: {}", e);
+            return;
+        }
+    };
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            Some(()) = hangup.recv() => {
+                info!("Received SIGHUP, reloading configuration");
+                let _ = tx.send(());
+            }
+            else => return,
+        }
+    }
+}
+
+/// What happened during one cycle, reported as a `scraper_health` point.
+#[derive(Default)]
+struct CycleStats {
+    fetch_latency: Option<Duration>,
+    http_status: Option<u16>,
+    points_written: usize,
+    write_latency: Option<Duration>,
+    cache_used: bool,
+    /// No fetch was attempted because the circuit is open; the cycle
+    /// counts as neither success nor failure.
+    skipped: bool,
+    /// The maintenance window the cycle ran in.
+    maintenance: Option<String>,
+    /// Scraping was paused with SIGUSR1 or [`set_paused`].
+    paused: bool,
+    /// Whether fetching succeeded, `None` if it was not attempted or its
+    /// outcome says nothing about the API, e.g. when rate limited.
+    fetched: Option<bool>,
+}
+
+impl CycleStats {
+    fn to_data_point(
+        &self,
+        source: &str,
+        tags: Option<&HashMap<String, String>>,
+        consecutive_failures: u32,
+        success: bool,
+    ) -> Result<DataPoint> {
+        let mut builder = DataPoint::builder("scraper_health").tag("source", source);
+        for (key, value) in tags.into_iter().flatten() {
+            builder = builder.tag(key, value);
+        }
+        
+        if let Some(latency) = self.fetch_latency {
+            builder = builder.field("fetch_latency_ms", latency.as_secs_f64() * 1000.0);
+        }
+        if let Some(status) = self.http_status {
+            builder = builder.field("http_status", status as i64);
+        }
+        if let Some(latency) = self.write_latency {
+            builder = builder.field("write_latency_ms", latency.as_secs_f64() * 1000.0);
+        }
+        
+        builder
+            .field("points_written", self.points_written as i64)
+            .field("consecutive_failures", consecutive_failures as i64)
+            .field("cache_used", self.cache_used)
+            .field("success", success)
+            .timestamp(Utc::now().timestamp_nanos_opt().unwrap())
+            .build()
+            .context("Failed to build scraper_health point")
+    }
+}
+
+/// A source client and the config it was built from.
+type CachedClient = Option<(Arc<AppConfig>, Arc<dyn Source>)>;
+
+/// The client in `slot`, kept across cycles so connections are reused and
+/// rebuilt only after the config has been reloaded. Fallbacks are not
+/// `archived`, as `msparking backfill` could not parse their responses.
+fn cached_client(
+    slot: &mut CachedClient,
+    config: &Arc<AppConfig>,
+    source: &SourceConfig,
+    archived: bool,
+) -> Result<Arc<dyn Source>> {
+    if let Some((built_for, client)) = slot
+        && Arc::ptr_eq(built_for, config)
+    {
+        return Ok(client.clone());
+    }
+    
+    let archive = config.archive.as_ref()
+        .filter(|archive| archived && archive.enabled)
+        .map(|archive| Archive::new(archive, source, config.timezone))
+        .transpose()?;
+    let client: Arc<dyn Source> = source::build(source, archive)?.into();
+    *slot = Some((config.clone(), client.clone()));
+    Ok(client)
+}
+
+/// How a source's cycles are timed.
+#[derive(Clone, PartialEq)]
+enum Timing {
+    Interval(u64),
+    Schedule(Box<Schedule>),
+}
+
+impl std::fmt::Display for Timing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Timing::Interval(secs) => write!(f, "Interval: {} seconds", secs),
+            Timing::Schedule(schedule) => write!(f, "Schedule: {}", schedule),
+        }
+    }
+}
+
+/// The source's own timing followed by one interval per group of areas
+/// with their own `scraping_interval_secs`.
+type Plan = Vec<(Timing, Option<BTreeSet<i32>>)>;
+
+/// One timer of a source task.
+struct Lane {
+    timing: Timing,
+    /// Areas this lane scrapes, `None` for the source's own lane.
+    areas: Option<BTreeSet<i32>>,
+    next_tick: time::Instant,
+    deadline: Option<time::Instant>,
+}
+
+/// The extra fetches left between two cycles of the source's own lane, for
+/// `samples_per_interval`.
+struct Sampling {
+    next: time::Instant,
+    step: Duration,
+    /// Including `next`.
+    left: u32,
+}
+
+impl Sampling {
+    /// Samples after the cycle due at `tick`, skipping any already missed
+    /// by a slow cycle.
+    fn after(tick: time::Instant, interval_secs: u64, samples: u32) -> Option<Self> {
+        if samples <= 1 {
+            return None;
+        }
+        let step = Duration::from_secs(interval_secs) / samples;
+        let mut sampling = Sampling { next: tick + step, step, left: samples - 1 };
+        while sampling.next <= time::Instant::now() {
+            sampling = sampling.advance()?;
+        }
+        Some(sampling)
+    }
+    
+    fn advance(self) -> Option<Self> {
+        (self.left > 1).then(|| Sampling { next: self.next + self.step, step: self.step, left: self.left - 1 })
+    }
+}
+
+/// The areas a cycle covers, depending on which lanes fired.
+#[derive(Default)]
+struct Due {
+    /// The source's own lane fired, covering every area without its own
+    /// interval.
+    source: bool,
+    /// Areas whose own lane fired.
+    areas: HashSet<i32>,
+    /// Every area that has its own lane.
+    own_interval: HashSet<i32>,
+}
+
+impl Due {
+    fn all() -> Self {
+        Due { source: true, ..Default::default() }
+    }
+    
+    fn includes(&self, area_code: i32) -> bool {
+        if self.own_interval.contains(&area_code) {
+            self.areas.contains(&area_code)
+        } else {
+            self.source
+        }
+    }
+}
+
+/// Tags of the cached values written in place of a failed or skipped fetch,
+/// or of areas missing from one.
+const FILL_TAGS: &[(&str, &str)] = &[("source", "cache")];
+
+/// Scrapes a single configured source on its own interval or schedule.
+struct SourceTask {
+    name: String,
+    config_rx: watch::Receiver<Arc<AppConfig>>,
+    writer: Writer,
+    metrics: Arc<Metrics>,
+    live: Live,
+    shutdown: CancellationToken,
+    cached_data: HashMap<i32, CachedArea>,
+    /// Recent freshly scraped values per area, for `delta_spaces` and
+    /// trend alerts.
+    recent: Recent,
+    alerter: Alerter,
+    detector: Detector,
+    filters: Filters,
+    windows: Windows,
+    forecaster: Forecaster,
+    rollup: Rollup,
+    daily_stats: DailyStats,
+    dedup: Dedup,
+    events: Events,
+    gaps: Gaps,
+    consecutive_failures: u32,
+    stats: CycleStats,
+    /// Set after the API rate limited us, with the time it asked us to
+    /// wait until.
+    rate_limited_until: Option<Instant>,
+    /// When fetches started failing, for `cache_on_failure_secs`.
+    failing_since: Option<DateTime<Utc>>,
+    breaker: CircuitBreaker,
+    /// The source client and the config it was built from.
+    client: CachedClient,
+    fallback_client: CachedClient,
+}
+
+impl SourceTask {
+    fn new(
+        name: String,
+        config_rx: watch::Receiver<Arc<AppConfig>>,
+        writer: Writer,
+        metrics: Arc<Metrics>,
+        live: Live,
+        shutdown: CancellationToken,
+    ) -> Self {
+        let mut task = SourceTask {
+            name,
+            config_rx,
+            writer,
+            metrics,
+            live,
+            shutdown,
+            cached_data: HashMap::new(),
+            recent: Recent::default(),
+            alerter: Alerter::default(),
+            detector: Detector::default(),
+            filters: Filters::default(),
+            windows: Windows::default(),
+            forecaster: Forecaster::default(),
+            rollup: Rollup::default(),
+            daily_stats: DailyStats::default(),
+            dedup: Dedup::default(),
+            events: Events::default(),
+            gaps: Gaps::default(),
+            consecutive_failures: 0,
+            stats: CycleStats::default(),
+            rate_limited_until: None,
+            failing_since: None,
+            breaker: CircuitBreaker::default(),
+            client: None,
+            fallback_client: None,
+        };
+        
+        if let Some(file) = task.cache_file() {
+            match file.load() {
+                Ok(data) => {
+                    if !data.is_empty() {
+                        info!("[{}] Loaded {} cached areas", task.name, data.len());
+                    }
+                    task.cached_data = data;
+                }
+                Err(e) => warn!("[{}] Failed to load cache, starting empty: {:#}", task.name, e),
+            }
+        }
+        
+        task
+    }
+    
+    fn cache_file(&self) -> Option<CacheFile> {
+        self.config_rx.borrow().cache.as_ref().map(|cache| CacheFile::new(cache, &self.name))
+    }
+    
+    /// Persisting the cache is best effort; a failure is logged and the
+    /// in-memory copy is kept.
+    fn save_cache(&self) {
+        if let Some(file) = self.cache_file()
+            && let Err(e) = file.save(&self.cached_data)
+        {
+            warn!("[{}] Failed to save cache: {:#}", self.name, e);
+        }
+    }
+    
+    /// Runs a single fetch-transform-write cycle for the areas in `due`.
+    async fn run_cycle(&mut self, due: &Due) -> Result<()> {
+        let span = info_span!("cycle", source = %self.name, outcome = field::Empty);
+        let started = Instant::now();
+        self.stats = CycleStats::default();
+        let result = self.cycle(due).instrument(span.clone()).await;
+        let skipped = result.is_ok() && self.stats.skipped;
+        
+        let outcome = match &result {
+            Ok(_) if skipped => "skipped",
+            Ok(_) => "ok",
+            Err(_) => "error",
+        };
+        let duration_ms = started.elapsed().as_millis() as u64;
+        span.record("outcome", outcome);
+        span.in_scope(|| info!(outcome, duration_ms, "[{}] Cycle finished in {} ms", self.name, duration_ms));
+        
+        #[cfg(unix)]
+        systemd::cycle_finished(result.is_ok() && !skipped);
+        
+        // Skipped cycles still start a pause.
+        let config = self.config_rx.borrow().clone();
+        let event = match (self.stats.paused, &self.stats.maintenance, self.stats.fetched) {
+            (true, _, _) => Some(Some(Event::Paused)),
+            (false, Some(window), _) => Some(Some(Event::Maintenance(window.clone()))),
+            (false, None, Some(false)) => Some(Some(Event::Outage)),
+            (false, None, Some(true)) => Some(None),
+            (false, None, None) => None,
+        };
+        if let Some(event) = event {
+            self.write_events(&config, event).await;
+        }
+        
+        if skipped {
+            return result;
+        }
+        self.metrics.record_cycle(&self.name, result.is_ok());
+        
+        if result.is_ok() {
+            self.consecutive_failures = 0;
+        } else {
+            self.consecutive_failures += 1;
+        }
+        
+        if let Some(alerts) = &config.alerts {
+            self.alerter.check_health(alerts, &config.notifiers, config.timezone, &self.name, &result);
+        }
+        
+        if config.influxdb.scraper_health {
+            self.write_health(&config, result.is_ok()).await;
+        }
+        
+        result
+    }
+    
+    /// Like health points, events are best effort. The current event is
+    /// tracked even while `influxdb.events` is off.
+    async fn write_events(&mut self, config: &AppConfig, event: Option<Event>) {
+        let tags = config.source(&self.name).map(|source| config.tags_for(source));
+        let points = self.events.update(&self.name, tags.as_ref(), event, Utc::now());
+        let written = match points {
+            Ok(points) if points.is_empty() || !config.influxdb.events => Ok(()),
+            Ok(points) => self.writer.write_influxdb(points).await,
+            Err(e) => Err(e),
+        };
+        
+        if let Err(e) = written {
+            warn!("[{}] Failed to write events: {:#}", self.name, e);
+        }
+    }
+    
+    /// Health points are best effort; failing to write one never fails the
+    /// cycle.
+    async fn write_health(&self, config: &AppConfig, success: bool) {
+        let tags = config.source(&self.name).map(|source| config.tags_for(source));
+        let written = match self.stats.to_data_point(&self.name, tags.as_ref(), self.consecutive_failures, success) {
+            Ok(point) => self.writer.write_influxdb(vec![point]).await,
+            Err(e) => Err(e),
+        };
+        
+        if let Err(e) = written {
+            warn!("[{}] Failed to write scraper health: {:#}", self.name, e);
+        }
+    }
+    
+    /// Publishes the samples to live subscribers and writes them to the
+    /// sinks, leaving out unchanged areas if `dedup` is set.
+    async fn write(&mut self, samples: Vec<Sample>, dedup: Option<&DedupConfig>) -> Result<()> {
+        self.live.publish(&samples, self.stats.cache_used);
+        
+        let (samples, unchanged) = match dedup {
+            Some(dedup) => self.dedup.split(dedup, samples),
+            None => (samples, Vec::new()),
+        };
+        if !unchanged.is_empty() {
+            debug!("[{}] Skipping {} unchanged areas", self.name, unchanged.len());
+        }
+        let tracks_gaps = self.config_rx.borrow().gaps.is_some();
+        if samples.is_empty() {
+            self.dedup.record(&[], &unchanged);
+            self.record_gaps(&unchanged).await;
+            return Ok(());
+        }
+        let written = if dedup.is_some() || tracks_gaps { samples.clone() } else { Vec::new() };
+        
+        let count = samples.len();
+        let started = Instant::now();
+        let result = self.writer.write(samples)
+            .instrument(info_span!("write", points = count))
+            .await;
+        self.stats.write_latency = Some(started.elapsed());
+        if result.is_ok() {
+            self.stats.points_written = count;
+            self.dedup.record(&written, &unchanged);
+            let covered: Vec<Sample> = written.into_iter().chain(unchanged).collect();
+            self.record_gaps(&covered).await;
+        } else {
+            self.metrics.record_write_error(&self.name);
+        }
+        result
+    }
+    
+    /// Reports the gaps closed by a successful write as `parking_events`
+    /// points, best effort like health points, and notifies if configured.
+    async fn record_gaps(&mut self, samples: &[Sample]) {
+        let config = self.config_rx.borrow().clone();
+        let Some(gaps) = &config.gaps else {
+            return;
+        };
+        let closed = self.gaps.record(gaps, samples);
+        if closed.is_empty() {
+            return;
+        }
+        
+        let tags = config.source(&self.name).map(|source| config.tags_for(source));
+        let mut points = Vec::new();
+        for gap in &closed {
+            warn!(
+                area_code = gap.area_code,
+                duration_secs = gap.duration_secs(),
+                "[{}] Area {} had no data for {:.0} s", self.name, gap.area_code, gap.duration_secs(),
+            );
+            match gap.to_data_point(&self.name, tags.as_ref()) {
+                Ok(point) => points.push(point),
+                Err(e) => warn!("[{}] Failed to build gap event: {:#}", self.name, e),
+            }
+            if gaps.notify {
+                self.alerter.notify_gap(gaps, &config.notifiers, config.timezone, &self.name, gap);
+            }
+        }
+        
+        if let Err(e) = self.writer.write_influxdb(points).await {
+            warn!("[{}] Failed to write gap events: {:#}", self.name, e);
+        }
+    }
+    
+    /// Fetches from the fallback source after the primary one failed.
+    async fn fetch_fallback(&mut self, config: &Arc<AppConfig>, fallback: &SourceConfig) -> Result<Fetched> {
+        let client = cached_client(&mut self.fallback_client, config, fallback, false)?;
+        let fetched = source::fetch_parking_data(client.as_ref(), &fallback.retry)
+            .instrument(info_span!("fetch_fallback"))
+            .await
+            .context("Error fetching from the fallback source")?;
+        if fetched.areas.is_empty() {
+            return Err(anyhow!("No parking data available from the fallback source"));
+        }
+        Ok(fetched)
+    }
+    
+    async fn cycle(&mut self, due: &Due) -> Result<()> {
+        let config = self.config_rx.borrow().clone();
+        let source = config.source(&self.name)
+            .ok_or_else(|| anyhow!("Source {} is no longer configured", self.name))?;
+        let name = &self.name.clone();
+        
+        if is_paused() {
+            self.stats.paused = true;
+            if source.cache_policy != CachePolicy::Never && self.has_fresh_cache(source) {
+                info!("[{}] Scraping paused, using cached data", name);
+                return self.write_cached(&config, source, due, &[]).await;
+            }
+            self.stats.skipped = true;
+            info!("[{}] Scraping paused, skipping this cycle", name);
+            return Ok(());
+        }
+        
+        info!("[{}] Fetching parking data...", name);
+        
+        let maintenance = source.maintenance(&config.maintenance);
+        if let Some(window) = maintenance.active_window(Utc::now(), config.timezone) {
+            self.stats.maintenance = Some(window.to_string());
+            if source.cache_policy == CachePolicy::Never {
+                info!(
+                    "[{}] Currently in maintenance window ({} {}), fetching anyway as the cache policy is never",
+                    name, window, maintenance.timezone(config.timezone),
+                );
+            } else {
+                info!(
+                    "[{}] Currently in maintenance window ({} {}), using cached data",
+                    name, window, maintenance.timezone(config.timezone),
+                );
+                
+                if self.cached_data.is_empty() {
+                    info!("[{}] No cached data available, attempting to fetch fresh data anyway", name);
+                } else if !self.has_fresh_cache(source) {
+                    warn!(
+                        "[{}] Cached data is older than {}s, attempting to fetch fresh data anyway",
+                        name, source.max_cache_age_secs.unwrap_or_default(),
+                    );
+                } else {
+                    return self.write_cached(&config, source, due, &[]).await;
+                }
+            }
+        }
+        
+        if let Some(until) = self.rate_limited_until {
+            if Instant::now() < until {
+                warn!(
+                    "[{}] Rate limited by the API for another {}s, skipping this cycle",
+                    name, until.saturating_duration_since(Instant::now()).as_millis().div_ceil(1000),
+                );
+                return self.fill_skipped(&config, source, due).await;
+            }
+            self.rate_limited_until = None;
+        }
+        
+        if let Some(breaker) = &source.circuit_breaker {
+            if !self.breaker.allow() {
+                self.stats.skipped = true;
+                debug!(
+                    "[{}] Circuit open for another {}s, skipping fetch",
+                    name, self.breaker.remaining().unwrap_or_default().as_millis().div_ceil(1000),
+                );
+                if breaker.use_cache && source.cache_policy != CachePolicy::Never && self.has_fresh_cache(source) {
+                    return self.write_cached(&config, source, due, &[]).await;
+                }
+                if self.cache_on_failure(source) {
+                    return self.write_cached(&config, source, due, FILL_TAGS).await;
+                }
+                return Ok(());
+            }
+            if self.breaker.state() == CircuitState::HalfOpen {
+                info!("[{}] Circuit half-open, sending a trial fetch", name);
+                self.metrics.set_circuit_state(name, CircuitState::HalfOpen);
+            }
+        }
+        
+        let started = Instant::now();
+        let span = info_span!("fetch", http_status = field::Empty);
+        let client = cached_client(&mut self.client, &config, source, true)?;
+        let fetched = source::fetch_parking_data(client.as_ref(), &source.retry)
+            .instrument(span.clone())
+            .await;
+        self.stats.fetch_latency = Some(started.elapsed());
+        self.stats.http_status = match &fetched {
+            Ok(fetched) => fetched.status,
+            Err(e) => source::http_status(e),
+        };
+        if let Some(status) = self.stats.http_status {
+            span.record("http_status", status);
+        }
+        let fetched = fetched
+            .context("Error fetching parking data")
+            .and_then(|fetched| {
+                if fetched.areas.is_empty() {
+                    return Err(anyhow!("No parking data available in the response"));
+                }
+                
+                Ok(fetched)
+            });
+        self.metrics.record_fetch(name, started.elapsed(), fetched.is_ok());
+        
+        if let Err(e) = &fetched
+            && let Some(limited) = source::rate_limited(e)
+        {
+            self.metrics.record_rate_limited(name);
+            self.rate_limited_until = limited.retry_after.map(|delay| Instant::now() + delay);
+            warn!("[{}] {}, skipping this cycle", name, limited);
+            return self.fill_skipped(&config, source, due).await;
+        }
+        
+        if let Some(breaker) = &source.circuit_breaker {
+            self.record_breaker(breaker, fetched.is_ok());
+        }
+        
+        let mut fallback_tags = None;
+        let fetched = match (fetched, &source.fallback) {
+            (Err(e), Some(fallback)) => {
+                warn!("[{}] {:#}, trying the fallback source", name, e);
+                fallback_tags = Some(&fallback.tags);
+                self.fetch_fallback(&config, fallback).await
+            }
+            (fetched, _) => fetched,
+        };
+        
+        self.stats.fetched = Some(fetched.is_ok());
+        let Fetched { mut areas, date, .. } = match fetched {
+            Ok(fetched) => {
+                self.failing_since = None;
+                fetched
+            }
+            Err(e) => {
+                self.failing_since.get_or_insert_with(Utc::now);
+                if self.cache_on_failure(source) {
+                    // The cycle still fails, so failure counts and health
+                    // alerts reflect the outage.
+                    info!("[{}] Fetch failed, writing cached data instead", name);
+                    if let Err(write_error) = self.write_cached(&config, source, due, FILL_TAGS).await {
+                        warn!("[{}] {:#}", name, write_error);
+                    }
+                }
+                return Err(e);
+            }
+        };
+        
+        areas.retain(|area| due.includes(area.area_code));
+        if areas.is_empty() {
+            info!("[{}] None of the fetched areas are due this cycle", name);
+            return Ok(());
+        }
+        
+        let mut smoothed = HashMap::new();
+        let mut rejected = HashSet::new();
+        for area in &mut areas {
+            let Some(area_config) = source.area(&config.areas, area.area_code) else {
+                continue;
+            };
+            let Some(filter) = &area_config.filter else {
+                continue;
+            };
+            match self.filters.apply(filter, area_config.capacity(), area.area_code, area.area_free_space_num) {
+                Ok(filtered) => {
+                    area.area_free_space_num = filtered.free_spaces;
+                    if let Some(value) = filtered.smoothed {
+                        smoothed.insert(area.area_code, value);
+                    }
+                }
+                Err(reason) => {
+                    warn!(
+                        area_code = area.area_code,
+                        free_spaces = area.area_free_space_num,
+                        "[{}] Rejected value for area {}: {}", name, area.area_code, reason,
+                    );
+                    rejected.insert(area.area_code);
+                }
+            }
+        }
+        if !rejected.is_empty() {
+            areas.retain(|area| !rejected.contains(&area.area_code));
+            if areas.is_empty() {
+                self.fill_missing(&config, source, due, &areas).await;
+                return Ok(());
+            }
+        }
+        
+        let mut suspect = HashSet::new();
+        if let Some(anomaly) = &source.anomaly {
+            for area in &areas {
+                if let Some(reason) = self.detector.check(anomaly, area.area_code, area.area_free_space_num) {
+                    let action = match anomaly.action {
+                        AnomalyAction::Tag => "tagging it as suspect",
+                        AnomalyAction::Drop => "dropping it",
+                    };
+                    warn!(
+                        area_code = area.area_code,
+                        free_spaces = area.area_free_space_num,
+                        "[{}] Implausible value for area {}: {}, {}", name, area.area_code, reason, action,
+                    );
+                    suspect.insert(area.area_code);
+                }
+            }
+            
+            if anomaly.action == AnomalyAction::Drop {
+                areas.retain(|area| !suspect.contains(&area.area_code));
+                if areas.is_empty() {
+                    self.fill_missing(&config, source, due, &areas).await;
+                    return Ok(());
+                }
+            }
+        }
+        
+        let now = Utc::now();
+        let keep = config.alerts.iter()
+            .flat_map(|alerts| &alerts.trends)
+            .map(|trend| trend.window_secs)
+            .chain(source.time_until_full.as_ref().map(|until_full| until_full.window_secs))
+            .map(Duration::from_secs)
+            .max()
+            .unwrap_or_default();
+        for area in &areas {
+            let location = source.location_for(&config.areas, area.area_code);
+            self.metrics.set_free_spaces(name, area.area_code, location, area.area_free_space_num);
+            self.recent.record(area.area_code, now, area.area_free_space_num, keep);
+            
+            if let Some(alerts) = &config.alerts
+                && !suspect.contains(&area.area_code)
+            {
+                let alert = Alert {
+                    source: name,
+                    area_code: area.area_code,
+                    location,
+                    free_spaces: area.area_free_space_num,
+                };
+                self.alerter.check(alerts, &config.notifiers, config.timezone, &alert);
+                self.alerter.check_trends(alerts, &config.notifiers, config.timezone, &alert, &self.recent);
+            }
+        }
+        
+        let timestamp = source.api_timestamp.as_ref().and_then(|api_timestamp| {
+            let date = date.as_deref().unwrap_or_default();
+            let parsed = api_timestamp.parse(date, config.timezone);
+            if parsed.is_none() {
+                warn!("[{}] Could not parse the API date {:?}, using the local time", name, date);
+            }
+            parsed
+        });
+        
+        let mut cache_updated = false;
+        for area in &areas {
+            if (area.area_free_space_num > 0 || source.cache_non_positive)
+                && source.cache_policy != CachePolicy::Never
+                && !suspect.contains(&area.area_code)
+            {
+                self.cached_data.insert(area.area_code, CachedArea { data: area.clone(), fetched_at: now });
+                cache_updated = true;
+            }
+        }
+        if cache_updated {
+            self.save_cache();
+        }
+        
+        let samples: Vec<Sample> = info_span!("transform").in_scope(|| {
+            areas
+                .iter()
+                .map(|area| {
+                    let delta = self.recent.delta_per_minute(area.area_code);
+                    let mut sample = sink::create_sample(area, source, &config, delta);
+                    if suspect.contains(&area.area_code) {
+                        sample.tags.insert("suspect".to_string(), "true".to_string());
+                    } else if source.forecast {
+                        self.forecaster.record(area.area_code, area.area_free_space_num, now, config.timezone);
+                    }
+                    if let Some(until_full) = &source.time_until_full {
+                        sample.until_full = self.recent.until_full(area.area_code, until_full);
+                    }
+                    sample.free_spaces_smoothed = smoothed.get(&area.area_code).copied();
+                    if source.samples_per_interval > 1 && !due.own_interval.contains(&area.area_code) {
+                        sample.window = Some(self.windows.take(area.area_code, area.area_free_space_num));
+                    }
+                    if source.forecast {
+                        sample.predicted_free_spaces = self.forecaster.predict(area.area_code, now, config.timezone);
+                    }
+                    if let Some(tags) = fallback_tags {
+                        sample.tags.extend(tags.iter().map(|(key, value)| (key.clone(), value.clone())));
+                    }
+                    if let Some(timestamp) = timestamp {
+                        sample.timestamp = timestamp;
+                        sample.data_lag_secs = Some((now - timestamp).as_seconds_f64());
+                    }
+                    sample
+                })
+                .collect()
+        });
+        
+        info!(
+            areas = samples.len(),
+            duration_ms = self.stats.fetch_latency.map(|d| d.as_millis() as u64),
+            "[{}] Found parking data for {} areas", name, samples.len(),
+        );
+        
+        for area in &areas {
+            info!(
+                area_code = area.area_code,
+                free_spaces = area.area_free_space_num,
+                "[{}] Area {}: {} free spaces", name, area.area_code, area.area_free_space_num,
+            );
+        }
+        
+        let observed: Vec<Sample> = samples.iter()
+            .filter(|sample| !suspect.contains(&sample.area_code))
+            .cloned()
+            .collect();
+        let rollups = self.rollups(&config, &observed);
+        
+        let result = self.write(samples, source.dedup.as_ref())
+            .await
+            .context("Failed to write to InfluxDB");
+        
+        // Written even if the samples were not, since the finished hour or
+        // day is gone from memory. Like health points, rollups never fail
+        // the cycle.
+        let written = match rollups {
+            Ok(points) if points.is_empty() => Ok(()),
+            Ok(points) => self.writer.write_influxdb(points).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            warn!("[{}] Failed to write rollups: {:#}", name, e);
+        }
+        
+        result?;
+        info!(duration_ms = self.write_ms(), "[{}] Successfully wrote data", name);
+        self.fill_missing(&config, source, due, &areas).await;
+        Ok(())
+    }
+    
+    fn has_fresh_cache(&self, source: &SourceConfig) -> bool {
+        let now = Utc::now();
+        self.cached_data.values().any(|cached| cached.is_fresh(source.max_cache_age_secs, now))
+    }
+    
+    /// Whether the cache policy fills in failed fetches, fetches have been
+    /// failing for no longer than `cache_on_failure_secs` and cached values
+    /// are left to write.
+    fn cache_on_failure(&self, source: &SourceConfig) -> bool {
+        let Some(since) = self.failing_since.filter(|_| source.cache_policy.on_failure()) else {
+            return false;
+        };
+        if let Some(secs) = source.cache_on_failure_secs
+            && (Utc::now() - since).num_seconds() > secs as i64
+        {
+            debug!("[{}] Fetches failing for more than {}s, no longer writing cached data", self.name, secs);
+            return false;
+        }
+        self.has_fresh_cache(source)
+    }
+    
+    /// Writes cached values for a cycle skipped after rate limiting, with
+    /// the `always_fill_gaps` policy.
+    async fn fill_skipped(&mut self, config: &AppConfig, source: &SourceConfig, due: &Due) -> Result<()> {
+        if source.cache_policy == CachePolicy::AlwaysFillGaps && self.has_fresh_cache(source) {
+            return self.write_cached(config, source, due, FILL_TAGS).await;
+        }
+        Ok(())
+    }
+    
+    /// Writes cached values for the due areas missing from a successful
+    /// fetch, with the `always_fill_gaps` policy. Never fails the cycle.
+    async fn fill_missing(&mut self, config: &AppConfig, source: &SourceConfig, due: &Due, fetched: &[AreaData]) {
+        if source.cache_policy != CachePolicy::AlwaysFillGaps {
+            return;
+        }
+        
+        let now = Utc::now();
+        let fetched: HashSet<i32> = fetched.iter().map(|area| area.area_code).collect();
+        let missing: HashSet<i32> = self.cached_data.values()
+            .filter(|cached| cached.is_fresh(source.max_cache_age_secs, now))
+            .map(|cached| cached.data.area_code)
+            .filter(|&area_code| due.includes(area_code) && !fetched.contains(&area_code))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        
+        let missing = Due { source: false, areas: missing.clone(), own_interval: missing };
+        if let Err(e) = self.write_cached(config, source, &missing, FILL_TAGS).await {
+            warn!("[{}] {:#}", self.name, e);
+        }
+    }
+    
+    /// Writes the cached areas in `due`, tagged `cached=true` and with the
+    /// extra `tags`, skipping those older than `max_cache_age_secs`.
+    async fn write_cached(
+        &mut self,
+        config: &AppConfig,
+        source: &SourceConfig,
+        due: &Due,
+        tags: &[(&str, &str)],
+    ) -> Result<()> {
+        let name = &self.name.clone();
+        let now = Utc::now();
+        let mut fresh = Vec::new();
+        for cached in self.cached_data.values().filter(|cached| due.includes(cached.data.area_code)) {
+            if cached.is_fresh(source.max_cache_age_secs, now) {
+                fresh.push(&cached.data);
+            } else {
+                warn!(
+                    "[{}] Cached data for area {} is {}s old, not writing it",
+                    name, cached.data.area_code, (now - cached.fetched_at).num_seconds(),
+                );
+            }
+        }
+        
+        let samples: Vec<Sample> = fresh.iter()
+            .map(|area| {
+                let mut sample = sink::create_sample(area, source, config, None);
+                sample.tags.insert("cached".to_string(), "true".to_string());
+                sample.tags.extend(tags.iter().map(|(key, value)| (key.to_string(), value.to_string())));
+                sample
+            })
+            .collect();
+        
+        info!("[{}] Using cached data for {} areas", name, samples.len());
+        self.stats.cache_used = true;
+        
+        for area in &fresh {
+            info!(
+                area_code = area.area_code,
+                free_spaces = area.area_free_space_num,
+                cached = true,
+                "[{}] Cached - Area {}: {} free spaces", name, area.area_code, area.area_free_space_num,
+            );
+        }
+        
+        self.write(samples, source.dedup.as_ref())
+            .await
+            .context("Failed to write cached data to InfluxDB")?;
+        
+        info!(duration_ms = self.write_ms(), "[{}] Successfully wrote cached data", name);
+        Ok(())
+    }
+    
+    /// Feeds a fetch outcome to the circuit breaker, logging once per state
+    /// change rather than on every skipped cycle.
+    fn record_breaker(&mut self, config: &config::CircuitBreakerConfig, success: bool) {
+        let before = self.breaker.state();
+        let failures = self.breaker.failures() + 1;
+        self.breaker.record(config, success);
+        let after = self.breaker.state();
+        
+        match (before, after) {
+            (CircuitState::HalfOpen, CircuitState::Open) => warn!(
+                "[{}] Trial fetch failed, circuit open for another {}s",
+                self.name, config.cooldown_secs,
+            ),
+            (_, CircuitState::Open) => warn!(
+                "[{}] Circuit opened after {} consecutive failed fetches, pausing requests for {}s",
+                self.name, failures, config.cooldown_secs,
+            ),
+            (CircuitState::HalfOpen, CircuitState::Closed) => {
+                info!("[{}] Trial fetch succeeded, circuit closed", self.name);
+            }
+            _ => {}
+        }
+        
+        if before != after {
+            self.metrics.set_circuit_state(&self.name, after);
+        }
+    }
+    
+    /// Points of the hours and days that ended before these samples.
+    fn rollups(&mut self, config: &AppConfig, samples: &[Sample]) -> Result<Vec<DataPoint>> {
+        let mut points = Vec::new();
+        if config.influxdb.hourly_rollup {
+            points.extend(self.rollup.add(samples)?);
+        }
+        if config.influxdb.daily_stats {
+            points.extend(self.daily_stats.add(samples, config.timezone)?);
+        }
+        Ok(points)
+    }
+    
+    fn write_ms(&self) -> Option<u64> {
+        self.stats.write_latency.map(|d| d.as_millis() as u64)
+    }
+    
+    fn plan(&self) -> Option<Plan> {
+        let config = self.config_rx.borrow();
+        let source = config.source(&self.name)?;
+        
+        let timing = match &source.schedule {
+            Some(schedule) => Timing::Schedule(Box::new(schedule.clone())),
+            None => Timing::Interval(source.scraping_interval_secs),
+        };
+        let mut plan = vec![(timing, None)];
+        plan.extend(
+            source.area_intervals(&config.areas)
+                .into_iter()
+                .map(|(secs, areas)| (Timing::Interval(secs), Some(areas))),
+        );
+        Some(plan)
+    }
+    
+    /// Lanes for `plan`. Interval lanes fire right away on startup and one
+    /// interval from now after a config change.
+    fn lanes(&self, plan: &Plan, startup: bool) -> Vec<Lane> {
+        let now = time::Instant::now();
+        plan.iter()
+            .map(|(timing, areas)| {
+                let next_tick = match timing {
+                    Timing::Interval(secs) if !startup => now + Duration::from_secs(self.interval(*secs, areas).0),
+                    _ => now,
+                };
+                Lane {
+                    timing: timing.clone(),
+                    areas: areas.clone(),
+                    next_tick,
+                    deadline: self.deadline(timing, areas, next_tick),
+                }
+            })
+            .collect()
+    }
+    
+    /// The interval in effect for a lane of `secs` and when it next changes,
+    /// per the source's `interval_overrides` for its own lane.
+    fn interval(&self, secs: u64, areas: &Option<BTreeSet<i32>>) -> (u64, Option<time::Instant>) {
+        let config = self.config_rx.borrow();
+        let Some(source) = config.source(&self.name).filter(|_| areas.is_none()) else {
+            return (secs, None);
+        };
+        let now = Utc::now();
+        let (secs, change) = source.interval_at(now, config.timezone);
+        let change = change.map(|change| time::Instant::now() + (change - now).to_std().unwrap_or_default());
+        (secs, change)
+    }
+    
+    /// When the next cycle of a lane is due. Intervals run at a fixed rate
+    /// from `next_tick` plus jitter; schedules at their next cron occurrence.
+    fn deadline(&self, timing: &Timing, areas: &Option<BTreeSet<i32>>, next_tick: time::Instant) -> Option<time::Instant> {
+        match timing {
+            Timing::Interval(secs) => {
+                let (secs, _) = self.interval(*secs, areas);
+                let config = self.config_rx.borrow();
+                let jitter = config.source(&self.name).map_or(Duration::ZERO, |source| source.jitter(secs));
+                Some(next_tick + jitter)
+            }
+            Timing::Schedule(schedule) => {
+                let config = self.config_rx.borrow();
+                let now = Utc::now();
+                let next = schedule.next_after(now, config.timezone)?;
+                Some(time::Instant::now() + (next - now).to_std().unwrap_or_default())
+            }
+        }
+    }
+    
+    fn samples_per_interval(&self) -> u32 {
+        let config = self.config_rx.borrow();
+        config.source(&self.name).map_or(1, |source| source.samples_per_interval)
+    }
+    
+    /// Fetches between two cycles for `samples_per_interval`, only recording
+    /// the values for the next write. A failed sample is not retried.
+    async fn sample(&mut self) {
+        let config = self.config_rx.borrow().clone();
+        let Some(source) = config.source(&self.name) else {
+            return;
+        };
+        let in_maintenance = source.maintenance(&config.maintenance)
+            .active_window(Utc::now(), config.timezone)
+            .is_some();
+        let rate_limited = self.rate_limited_until.is_some_and(|until| Instant::now() < until);
+        if is_paused() || in_maintenance || rate_limited || self.breaker.state() == CircuitState::Open {
+            return;
+        }
+        
+        let client = match cached_client(&mut self.client, &config, source, true) {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("[{}] {:#}", self.name, e);
+                return;
+            }
+        };
+        let retry = RetryConfig { max_attempts: 1, ..source.retry.clone() };
+        let started = Instant::now();
+        let fetched = source::fetch_parking_data(client.as_ref(), &retry)
+            .instrument(info_span!("sample", source = %self.name))
+            .await;
+        self.metrics.record_fetch(&self.name, started.elapsed(), fetched.is_ok());
+        
+        match fetched {
+            Ok(fetched) => {
+                // Areas on their own interval are not sampled, their lanes
+                // write without a window.
+                let own_interval: HashSet<i32> = source.area_intervals(&config.areas)
+                    .into_values()
+                    .flatten()
+                    .collect();
+                for area in fetched.areas.iter().filter(|area| !own_interval.contains(&area.area_code)) {
+                    if let Some(value) = self.check_sample(&config, source, area) {
+                        self.windows.record(area.area_code, value);
+                    }
+                }
+                debug!("[{}] Sampled {} areas", self.name, fetched.areas.len());
+            }
+            Err(e) => warn!("[{}] Failed to sample parking data: {:#}", self.name, e),
+        }
+    }
+    
+    /// The value to record for a sampled area, after the same filters and
+    /// anomaly rules as written values, or `None` if they reject it.
+    fn check_sample(&mut self, config: &AppConfig, source: &SourceConfig, area: &AreaData) -> Option<i64> {
+        let mut value = area.area_free_space_num;
+        if let Some(area_config) = source.area(&config.areas, area.area_code)
+            && let Some(filter) = &area_config.filter
+        {
+            match self.filters.check_sample(filter, area_config.capacity(), area.area_code, value) {
+                Ok(filtered) => value = filtered,
+                Err(reason) => {
+                    debug!("[{}] Rejected sample for area {}: {}", self.name, area.area_code, reason);
+                    return None;
+                }
+            }
+        }
+        if let Some(anomaly) = &source.anomaly
+            && let Some(reason) = self.detector.check_sample(anomaly, area.area_code, value)
+        {
+            debug!("[{}] Implausible sample for area {}: {}", self.name, area.area_code, reason);
+            return None;
+        }
+        Some(value)
+    }
+    
+    fn log_plan(&self, plan: &Plan) {
+        for (timing, areas) in plan {
+            match areas {
+                None => info!("[{}] {}", self.name, timing),
+                Some(areas) => info!("[{}] Areas {:?}: {}", self.name, areas, timing),
+            }
+        }
+        
+        let config = self.config_rx.borrow();
+        for interval in config.source(&self.name).iter().flat_map(|source| &source.interval_overrides) {
+            info!("[{}] Interval: {} seconds during {}", self.name, interval.interval_secs, interval.window);
+        }
+    }
+    
+    async fn run(mut self) {
+        let Some(mut plan) = self.plan() else {
+            return;
+        };
+        let mut lanes = self.lanes(&plan, true);
+        let mut sampling: Option<Sampling> = None;
+        
+        info!("[{}] Starting parking data scraper", self.name);
+        self.log_plan(&plan);
+        
+        loop {
+            let next = lanes.iter().filter_map(|lane| lane.deadline).min();
+            if next.is_none() {
+                warn!("[{}] Schedule has no future occurrences, idling", self.name);
+            }
+            let sleep = async {
+                match next {
+                    Some(deadline) => time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+            let sample_at = sampling.as_ref().map(|sampling| sampling.next);
+            let sample = async {
+                match sample_at {
+                    Some(at) => time::sleep_until(at).await,
+                    None => std::future::pending().await,
+                }
+            };
+            
+            tokio::select! {
+                _ = self.shutdown.cancelled() => {
+                    self.save_cache();
+                    info!("[{}] Stopped", self.name);
+                    return;
+                }
+                _ = sleep => {
+                    // Lanes due at the same time share a single fetch.
+                    let now = time::Instant::now();
+                    let fired: Vec<usize> = (0..lanes.len())
+                        .filter(|&i| lanes[i].deadline.is_some_and(|deadline| deadline <= now))
+                        .collect();
+                    
+                    let mut due = Due::default();
+                    for (i, lane) in lanes.iter().enumerate() {
+                        let fired = fired.contains(&i);
+                        match &lane.areas {
+                            None => due.source = fired,
+                            Some(areas) => {
+                                due.own_interval.extend(areas);
+                                if fired {
+                                    due.areas.extend(areas);
+                                }
+                            }
+                        }
+                    }
+                    
+                    if let Err(e) = self.run_cycle(&due).await {
+                        error!("[{}] {:#}", self.name, e);
+                    }
+                    // Samples of areas that were not written, or not
+                    // fetched at all, start over with the next interval.
+                    if due.source {
+                        self.windows.clear();
+                    }
+                    
+                    for i in fired {
+                        if let Timing::Interval(secs) = lanes[i].timing {
+                            let (secs, change) = self.interval(secs, &lanes[i].areas);
+                            if lanes[i].areas.is_none() {
+                                sampling = Sampling::after(lanes[i].next_tick, secs, self.samples_per_interval());
+                            }
+                            // Ticks missed while a slow cycle ran are skipped.
+                            // A change of interval gets a tick of its own.
+                            let next_tick = lanes[i].next_tick + Duration::from_secs(secs);
+                            lanes[i].next_tick = change.map_or(next_tick, |change| next_tick.min(change))
+                                .max(time::Instant::now());
+                        }
+                        lanes[i].deadline = self.deadline(&lanes[i].timing, &lanes[i].areas, lanes[i].next_tick);
+                    }
+                }
+                _ = sample => {
+                    self.sample().await;
+                    sampling = sampling.and_then(Sampling::advance);
+                }
+                changed = self.config_rx.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    
+                    let Some(new_plan) = self.plan() else {
+                        return;
+                    };
+                    
+                    if new_plan != plan {
+                        plan = new_plan;
+                        lanes = self.lanes(&plan, false);
+                        sampling = None;
+                        info!("[{}] Scraping timing changed", self.name);
+                        self.log_plan(&plan);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A running source task, stopped through its own child of the shutdown
+/// token so it saves its cache on the way out.
+struct SourceHandle {
+    handle: JoinHandle<()>,
+    stop: CancellationToken,
+}
+
+/// Spawns tasks for newly configured sources and stops tasks whose source
+/// was removed from the configuration.
+async fn reconcile_sources(
+    tasks: &mut HashMap<String, SourceHandle>,
+    config: &AppConfig,
+    config_rx: &watch::Receiver<Arc<AppConfig>>,
+    writer: &Writer,
+    metrics: &Arc<Metrics>,
+    live: &Live,
+    shutdown: &CancellationToken,
+) {
+    let removed: Vec<String> = tasks.keys()
+        .filter(|name| config.source(name).is_none())
+        .cloned()
+        .collect();
+    for name in removed {
+        if let Some(task) = tasks.remove(&name) {
+            info!("[{}] Source removed from configuration, stopping", name);
+            task.stop.cancel();
+            let _ = task.handle.await;
+        }
+    }
+    
+    for source in config.sources() {
+        if !tasks.contains_key(&source.name) {
+            let stop = shutdown.child_token();
+            let task = SourceTask::new(
+                source.name.clone(),
+                config_rx.clone(),
+                writer.clone(),
+                metrics.clone(),
+                live.clone(),
+                stop.clone(),
+            );
+            tasks.insert(source.name.clone(), SourceHandle { handle: tokio::spawn(task.run()), stop });
+        }
+    }
+}
+
+/// Set while scraping is paused, see [`set_paused`].
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Pauses or resumes scraping in every source. Paused cycles never fetch;
+/// they write cached values like a maintenance window, if the cache policy
+/// allows it. SIGUSR1 pauses and SIGUSR2 resumes on Unix.
+pub fn set_paused(paused: bool) {
+    if PAUSED.swap(paused, Ordering::Relaxed) != paused {
+        if paused {
+            info!("Scraping paused");
+        } else {
+            info!("Scraping resumed");
+        }
+    }
+}
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Pauses on SIGUSR1 and resumes on SIGUSR2 until shutdown.
+#[cfg(unix)]
+async fn pause_signals(shutdown: CancellationToken) {
+    use tokio::signal::unix::{SignalKind, signal};
+    
+    let (mut pause, mut resume) = match (signal(SignalKind::user_defined1()), signal(SignalKind::user_defined2())) {
+        (Ok(pause), Ok(resume)) => (pause, resume),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Failed to install SIGUSR1/SIGUSR2 handlers, pausing is unavailable: {}", e);
+            return;
+        }
+    };
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            Some(()) = pause.recv() => set_paused(true),
+            Some(()) = resume.recv() => set_paused(false),
+            else => return,
+        }
+    }
+}
+
+/// Resolves on SIGINT, or SIGTERM on Unix.
+async fn shutdown_signal() {
+    let ctrl_c = tokio::signal::ctrl_c();
+    
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to install SIGTERM handler: {}", e);
+                futures::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = futures::future::pending::<()>();
+    
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+pub async fn run_scraper(config: AppConfig, config_path: &str, dry_run: bool) -> Result<()> {
+    run_scraper_until(config, config_path, dry_run, shutdown_signal()).await
+}
+
+/// Like [`run_scraper`], but shuts down once `stop` completes instead of on
+/// Ctrl-C or SIGTERM, e.g. when a Windows service is stopped.
+pub async fn run_scraper_until(
+    mut config: AppConfig,
+    config_path: &str,
+    dry_run: bool,
+    stop: impl Future<Output = ()>,
+) -> Result<()> {
+    ensure_bucket(&config, dry_run).await;
+    if config.startup_check {
+        run_check(&config, dry_run).await.context("Startup check failed")?;
+    }
+    
+    let metrics = Arc::new(Metrics::default());
+    let shutdown = CancellationToken::new();
+    let live = Live::default();
+    let http_listen = config.http.as_ref().map(|http| http.listen.clone());
+    let grpc_listen = config.grpc.as_ref().map(|grpc| grpc.listen.clone());
+    
+    let leader = config.leader.take();
+    
+    let (config_tx, config_rx) = watch::channel(Arc::new(config));
+    
+    if let Some(listen) = http_listen {
+        server::spawn(&listen, config_rx.clone(), metrics.clone(), live.clone(), shutdown.clone()).await?;
+    }
+    
+    if let Some(listen) = grpc_listen {
+        grpc::spawn(&listen, live.clone(), shutdown.clone()).await?;
+    }
+    
+    if config_rx.borrow().vault.is_some() {
+        tokio::spawn(vault::renew(shutdown.clone()));
+    }
+    
+    tokio::spawn(alerts::watch_conditions(config_rx.clone(), live.clone(), shutdown.clone()));
+    #[cfg(unix)]
+    tokio::spawn(pause_signals(shutdown.clone()));
+    
+    tokio::pin!(stop);
+    
+    // A standby only serves the endpoints above until it takes over.
+    let _leadership = match &leader {
+        Some(leader) => match leader::acquire(leader, &mut stop).await? {
+            Some(leadership) => Some(leadership),
+            None => {
+                shutdown.cancel();
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    
+    let (writer, writer_handle) = Writer::spawn(config_rx.clone(), metrics.clone(), dry_run)?;
+    if !dry_run {
+        tokio::spawn(alerts::watch_writes(config_rx.clone(), metrics.clone(), shutdown.clone()));
+    }
+    
+    // Keep the watcher alive for as long as the loop runs.
+    let (reload_tx, mut reload_rx) = mpsc::unbounded_channel();
+    let _watcher = match watch_config(config_path, reload_tx.clone()) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            warn!("Reloading on config file changes disabled: {:#}", e);
+            None
+        }
+    };
+    #[cfg(unix)]
+    tokio::spawn(reload_signal(reload_tx, shutdown.clone()));
+    #[cfg(not(unix))]
+    drop(reload_tx);
+    
+    let mut tasks = HashMap::new();
+    let config = config_rx.borrow().clone();
+    reconcile_sources(&mut tasks, &config, &config_rx, &writer, &metrics, &live, &shutdown).await;
+    #[cfg(unix)]
+    systemd::check_watchdog(&config_rx.borrow());
+    
+    loop {
+        tokio::select! {
+            _ = &mut stop => break,
+            Some(()) = reload_rx.recv() => {
+                // A single save usually produces a burst of events.
+                time::sleep(Duration::from_millis(200)).await;
+                while reload_rx.try_recv().is_ok() {}
+                
+                match config::load_config(config_path).await {
+                    Ok(config) => {
+                        // Running sources keep their cache and other state
+                        // and pick up the new config from their next cycle.
+                        let changes = config::diff(&config_rx.borrow(), &config);
+                        let config = Arc::new(config);
+                        config_tx.send_replace(config.clone());
+                        reconcile_sources(&mut tasks, &config, &config_rx, &writer, &metrics, &live, &shutdown).await;
+                        if changes.is_empty() {
+                            info!("Configuration reloaded, nothing changed");
+                        } else {
+                            info!("Configuration reloaded, {} changes", changes.len());
+                            for change in changes {
+                                info!("Config change: {}", change);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to reload configuration, keeping the current one: {:#}", e);
+                    }
+                }
+            }
+        }
+    }
+    
+    let timeout = Duration::from_secs(config_rx.borrow().shutdown_timeout_secs);
+    info!("Shutdown requested, waiting up to {:?} for pending work", timeout);
+    #[cfg(unix)]
+    systemd::stopping();
+    shutdown.cancel();
+    
+    // The writer drains its queue and exits once every source task has
+    // finished and dropped its handle.
+    drop(writer);
+    let drain = async {
+        for (_, task) in tasks {
+            let _ = task.handle.await;
+        }
+        let _ = writer_handle.await;
+    };
+    
+    match time::timeout(timeout, drain).await {
+        Ok(_) => info!("Shutdown complete"),
+        Err(_) => warn!("Shutdown timed out, exiting with work still pending"),
+    }
+    
+    Ok(())
+}
+
+/// Creates the InfluxDB bucket if configured to and missing. Failures are
+/// only logged, as InfluxDB may just not be up yet.
+async fn ensure_bucket(config: &AppConfig, dry_run: bool) {
+    let Some(create) = &config.influxdb.create_bucket else {
+        return;
+    };
+    if !config.influxdb.enabled || dry_run {
+        return;
+    }
+    
+    let created = match InfluxSink::new(&config.influxdb, None, dry_run) {
+        Ok(influxdb) => influxdb.ensure_bucket(create.retention_secs).await,
+        Err(e) => Err(e),
+    };
+    match created {
+        Ok(true) => info!("Created InfluxDB bucket {}", config.influxdb.bucket),
+        Ok(false) => debug!("InfluxDB bucket {} exists", config.influxdb.bucket),
+        Err(e) => warn!("Failed to ensure InfluxDB bucket {}: {:#}", config.influxdb.bucket, e),
+    }
+}
+
+/// Checks that InfluxDB accepts writes and fetches once from every source,
+/// so a misconfigured deployment fails right away rather than on its first
+/// cycle. Sources in a maintenance window are not fetched from.
+pub async fn run_check(config: &AppConfig, dry_run: bool) -> Result<()> {
+    if config.influxdb.enabled && !dry_run {
+        InfluxSink::new(&config.influxdb, None, dry_run)?
+            .check()
+            .await
+            .context("InfluxDB check failed")?;
+        info!("InfluxDB check passed");
+    }
+    
+    for source in config.sources() {
+        let maintenance = source.maintenance(&config.maintenance);
+        if let Some(window) = maintenance.active_window(Utc::now(), config.timezone) {
+            info!("[{}] Skipping the test fetch during maintenance window {}", source.name, window);
+            continue;
+        }
+        
+        let client = source::build(source, None)?;
+        let fetched = source::fetch_parking_data(client.as_ref(), &source.retry)
+            .await
+            .with_context(|| format!("Test fetch from source {} failed, check its url and credentials", source.name))?;
+        if fetched.areas.is_empty() {
+            return Err(anyhow!("Test fetch from source {} returned no areas", source.name));
+        }
+        info!("[{}] Test fetch returned {} areas", source.name, fetched.areas.len());
+    }
+    
+    Ok(())
+}
+
+/// Returned by [`run_once`] when at least one source failed its cycle, as
+/// opposed to errors that prevented the run from starting.
+#[derive(Debug)]
+pub struct SourcesFailed {
+    pub failed: usize,
+    pub total: usize,
+}
+
+impl std::fmt::Display for SourcesFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} of {} sources failed", self.failed, self.total)
+    }
+}
+
+impl std::error::Error for SourcesFailed {}
+
+/// Runs one cycle for every configured source and reports whether all of
+/// them succeeded.
+pub async fn run_once(config: AppConfig, dry_run: bool) -> Result<()> {
+    ensure_bucket(&config, dry_run).await;
+    let names: Vec<String> = config.sources().map(|source| source.name.clone()).collect();
+    let (_config_tx, config_rx) = watch::channel(Arc::new(config));
+    let metrics = Arc::new(Metrics::default());
+    let (writer, writer_handle) = Writer::spawn(config_rx.clone(), metrics.clone(), dry_run)?;
+    
+    let shutdown = CancellationToken::new();
+    
+    let mut failed = 0;
+    for name in &names {
+        let mut task = SourceTask::new(
+            name.clone(),
+            config_rx.clone(),
+            writer.clone(),
+            metrics.clone(),
+            Live::default(),
+            shutdown.clone(),
+        );
+        if let Err(e) = task.run_cycle(&Due::all()).await {
+            error!("[{}] {:#}", name, e);
+            failed += 1;
+        }
+    }
+    
+    // Let the writer close its sinks so queued messages are delivered.
+    drop(writer);
+    let _ = writer_handle.await;
+    
+    if failed > 0 {
+        return Err(SourcesFailed { failed, total: names.len() }.into());
+    }
+    
+    Ok(())
+}
+
+/// Pushes every sample the SQLite sink has not exported yet to InfluxDB.
+pub async fn run_export(config: AppConfig, dry_run: bool) -> Result<()> {
+    let sqlite = config.sqlite.as_ref()
+        .ok_or_else(|| anyhow!("Nothing to export, no [sqlite] section configured"))?;
+    let store = SqliteSink::new(sqlite)?;
+    let mut influxdb = InfluxSink::new(&config.influxdb, None, dry_run)?;
+    
+    let exported = store.export(&mut influxdb, dry_run).await?;
+    info!("Export complete, {} samples written to InfluxDB", exported);
+    
+    Ok(())
+}
+
+
+/// Writes the areas of previously saved API responses in `dir` to InfluxDB,
+/// using the mappings and tags of `source` (the first configured source if
+/// unset). Points are timestamped with the response's `date` or, if it has
+/// none, the file's modification time. Files that cannot be read are
+/// skipped.
+pub async fn run_backfill(config: AppConfig, dir: &str, source: Option<&str>, dry_run: bool) -> Result<()> {
+    let source = match source {
+        Some(name) => config.source(name).ok_or_else(|| anyhow!("Unknown source {}", name))?,
+        None => config.sources().next().ok_or_else(|| anyhow!("No sources configured"))?,
+    };
+    let mut influxdb = InfluxSink::new(&config.influxdb, None, dry_run)?;
+    
+    let mut paths = Vec::new();
+    backfill_files(Path::new(dir), &mut paths)
+        .with_context(|| format!("Failed to read backfill directory {}", dir))?;
+    paths.sort();
+    
+    let mut written = 0;
+    let mut skipped = 0;
+    for path in &paths {
+        let samples = match archived_samples(path, source, &config) {
+            Ok(samples) => samples,
+            Err(e) => {
+                warn!("Skipping {}: {:#}", path.display(), e);
+                skipped += 1;
+                continue;
+            }
+        };
+        
+        influxdb.write_points(&samples)
+            .await
+            .with_context(|| format!("Failed to write points from {}", path.display()))?;
+        written += samples.len();
+    }
+    
+    info!(
+        "Backfill complete, {} samples from {} files written to InfluxDB, {} files skipped",
+        written, paths.len() - skipped, skipped,
+    );
+    
+    Ok(())
+}
+
+/// Saved responses (`*.json`) and archived ones (`*.gz`) in `dir` and its
+/// subdirectories, which sort by time for the archive's dated layout.
+fn backfill_files(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            backfill_files(&path, paths)?;
+        } else if path.extension().is_some_and(|ext| ext == "json" || ext == "gz") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn archived_samples(path: &Path, source: &SourceConfig, config: &AppConfig) -> Result<Vec<Sample>> {
+    let body = if path.extension().is_some_and(|ext| ext == "gz") {
+        let mut body = String::new();
+        GzDecoder::new(fs::File::open(path).context("Failed to open file")?)
+            .read_to_string(&mut body)
+            .context("Failed to decompress file")?;
+        body
+    } else {
+        fs::read_to_string(path).context("Failed to read file")?
+    };
+    let archived = source::parse_archived(source, &body)?;
+    
+    let api_timestamp = source.api_timestamp.clone().unwrap_or_default();
+    let timestamp = match archived.date.and_then(|date| api_timestamp.parse(&date, config.timezone)) {
+        Some(date) => date,
+        None => fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map(DateTime::<Utc>::from)
+            .context("Response has no date and the file has no modification time")?,
+    };
+    
+    Ok(archived.areas
+        .iter()
+        .map(|area| {
+            let mut sample = sink::create_sample(area, source, config, None);
+            sample.timestamp = timestamp;
+            sample
+        })
+        .collect())
+}
@@ -0,0 +1,197 @@
+use crate::config::AppConfig;
+use crate::live::{self, Live};
+use crate::metrics::Metrics;
+use crate::sink::Sample;
+use anyhow::{Context, Result};
+use axum::{Json, Router};
+use axum::extract::State;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::http::{StatusCode, header};
+use axum::response::{Html, IntoResponse};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::get;
+use chrono::Utc;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::watch;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::{Stream, StreamExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+#[derive(Clone)]
+struct AppState {
+    config: watch::Receiver<Arc<AppConfig>>,
+    metrics: Arc<Metrics>,
+    live: Live,
+}
+
+/// Binds the embedded HTTP server and serves it in the background.
+pub async fn spawn(
+    listen: &str,
+    config: watch::Receiver<Arc<AppConfig>>,
+    metrics: Arc<Metrics>,
+    live: Live,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let app = Router::new()
+        .route("/", get(dashboard_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/ws", get(ws_handler))
+        .route("/events", get(events_handler))
+        .route("/api/v1/latest", get(latest_handler))
+        .route("/api/v1/history", get(history_handler))
+        .with_state(AppState { config, metrics, live });
+
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("Failed to bind HTTP server to {}", listen))?;
+
+    info!("HTTP server listening on {}", listen);
+
+    tokio::spawn(async move {
+        let server = axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown.cancelled_owned());
+        if let Err(e) = server.await {
+            error!("HTTP server stopped: {}", e);
+        }
+    });
+
+    Ok(())
+}
+
+async fn dashboard_handler() -> Html<&'static str> {
+    Html(include_str!("../static/dashboard.html"))
+}
+
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
+
+async fn healthz_handler() -> &'static str {
+    "ok"
+}
+
+/// Ready once every source has completed a cycle within the last
+/// `ready_max_missed_intervals` intervals and the last InfluxDB write went
+/// through. Sources on a cron `schedule` may idle for hours, so for them
+/// only that many consecutive failed cycles count. Responds 503 with one
+/// line per problem otherwise.
+async fn readyz_handler(State(state): State<AppState>) -> (StatusCode, String) {
+    let config = state.config.borrow().clone();
+    let missed = config.http.as_ref().map_or(3, |http| http.ready_max_missed_intervals);
+    let now = Utc::now();
+    let mut problems = Vec::new();
+
+    for source in config.sources() {
+        if source.schedule.is_some() {
+            let failures = state.metrics.consecutive_failures(&source.name);
+            if failures >= missed {
+                problems.push(format!("source {}: last {} cycles failed", source.name, failures));
+            }
+            continue;
+        }
+
+        let max_age = (source.longest_interval_secs() * missed as u64) as i64;
+        match state.metrics.last_success(&source.name) {
+            Some(at) if (now - at).num_seconds() <= max_age => {}
+            Some(at) => problems.push(format!(
+                "source {}: last successful scrape {}s ago",
+                source.name,
+                (now - at).num_seconds(),
+            )),
+            None => problems.push(format!("source {}: no successful scrape yet", source.name)),
+        }
+    }
+
+    if config.influxdb.enabled {
+        match state.metrics.influxdb_up() {
+            Some(true) => {}
+            Some(false) => problems.push("influxdb: last write failed".to_string()),
+            None => problems.push("influxdb: no write attempted yet".to_string()),
+        }
+    }
+
+    if problems.is_empty() {
+        (StatusCode::OK, "ready\n".to_string())
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, problems.join("\n") + "\n")
+    }
+}
+
+/// The most recent sample per area, with a `cached` flag for values
+/// written from the cache.
+async fn latest_handler(State(state): State<AppState>) -> Json<Vec<serde_json::Value>> {
+    let latest = state.live.latest()
+        .iter()
+        .map(|latest| {
+            let mut value = live::to_json(&latest.sample);
+            value["cached"] = latest.cached.into();
+            value
+        })
+        .collect();
+    Json(latest)
+}
+
+/// Free spaces per area over the last hour, as `[unix_ms, free_spaces]`
+/// pairs.
+async fn history_handler(State(state): State<AppState>) -> Json<Vec<serde_json::Value>> {
+    let history = state.live.history()
+        .into_iter()
+        .map(|history| {
+            let values: Vec<(i64, i64)> = history.values.iter()
+                .map(|(at, free_spaces)| (at.timestamp_millis(), *free_spaces))
+                .collect();
+            serde_json::json!({ "source": history.source, "area_code": history.area_code, "values": values })
+        })
+        .collect();
+    Json(history)
+}
+
+/// Pushes every new sample to the client as a JSON text message.
+async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
+    let samples = state.live.subscribe();
+    ws.on_upgrade(move |socket| stream_samples(socket, samples))
+}
+
+async fn stream_samples(mut socket: WebSocket, mut samples: broadcast::Receiver<Sample>) {
+    loop {
+        tokio::select! {
+            received = samples.recv() => match received {
+                Ok(sample) => {
+                    if socket.send(Message::Text(live::to_json(&sample).to_string().into())).await.is_err() {
+                        return;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("WebSocket client fell behind, skipped {} samples", skipped);
+                }
+                Err(RecvError::Closed) => return,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Streams every new sample as a `sample` event with the same JSON as `/ws`.
+async fn events_handler(State(state): State<AppState>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = BroadcastStream::new(state.live.subscribe()).filter_map(|item| match item {
+        Ok(sample) => Some(Ok(Event::default().event("sample").data(live::to_json(&sample).to_string()))),
+        Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+            warn!("SSE client fell behind, skipped {} samples", skipped);
+            None
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,176 @@
+use crate::config::AppConfig;
+use crate::scheduler;
+use anyhow::{Context, Result, anyhow};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+/// Name of the service, and of the event log source it logs to.
+pub const SERVICE_NAME: &str = "msparking";
+
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+/// What `run` hands over to the service thread started by the dispatcher.
+struct Launch {
+    config: AppConfig,
+    config_path: String,
+    runtime: Handle,
+}
+
+static LAUNCH: Mutex<Option<Launch>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Registers the current executable as an automatically started service
+/// that runs with `--service run` and the given config, made absolute since
+/// services start in the system directory.
+pub fn install(config_path: &str, log_level: Option<&str>) -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE)
+        .context("Failed to connect to the service manager, run as Administrator")?;
+
+    let executable_path = std::env::current_exe().context("Failed to locate the executable")?;
+    let config_path = std::env::current_dir()
+        .context("Failed to read the current directory")?
+        .join(config_path);
+
+    let mut launch_arguments = vec![
+        OsString::from("--service"),
+        OsString::from("run"),
+        OsString::from("--config"),
+        config_path.into_os_string(),
+    ];
+    if let Some(log_level) = log_level {
+        launch_arguments.extend([OsString::from("--log-level"), OsString::from(log_level)]);
+    }
+
+    let service = manager.create_service(
+        &ServiceInfo {
+            name: OsString::from(SERVICE_NAME),
+            display_name: OsString::from("MS Parking Data Scrubbing Agent"),
+            service_type: SERVICE_TYPE,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path,
+            launch_arguments,
+            dependencies: vec![],
+            account_name: None,
+            account_password: None,
+        },
+        ServiceAccess::CHANGE_CONFIG,
+    )
+    .context("Failed to create the service")?;
+    service.set_description("Scrapes parking space availability into InfluxDB")
+        .context("Failed to set the service description")?;
+
+    println!("Service {} installed. Register its event log source once with:", SERVICE_NAME);
+    println!("  New-EventLog -LogName Application -Source {}", SERVICE_NAME);
+    println!("and start it with:");
+    println!("  sc.exe start {}", SERVICE_NAME);
+    Ok(())
+}
+
+/// Stops the service if it is running and removes it.
+pub fn uninstall() -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .context("Failed to connect to the service manager, run as Administrator")?;
+    let service = manager.open_service(SERVICE_NAME, ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE)
+        .with_context(|| format!("Failed to open service {}", SERVICE_NAME))?;
+
+    // Marked for deletion now, removed once stopped and all handles closed.
+    service.delete().context("Failed to delete the service")?;
+    if service.query_status().context("Failed to query the service")?.current_state != ServiceState::Stopped {
+        service.stop().context("Failed to stop the service")?;
+    }
+
+    println!("Service {} uninstalled", SERVICE_NAME);
+    Ok(())
+}
+
+/// Changes to the executable's directory, so relative paths in the config
+/// such as cache or buffer directories end up next to it rather than in
+/// the system directory.
+pub fn enter_install_dir() -> Result<()> {
+    let executable = std::env::current_exe().context("Failed to locate the executable")?;
+    let dir = executable.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+    std::env::set_current_dir(&dir)
+        .with_context(|| format!("Failed to change to {}", dir.display()))
+}
+
+/// Hands the process over to the service control manager and blocks until
+/// the service stops. Must be called from within the Tokio runtime, which
+/// the service thread then runs the scraper on.
+pub fn run(config: AppConfig, config_path: &str) -> Result<()> {
+    *LAUNCH.lock().unwrap() = Some(Launch {
+        config,
+        config_path: config_path.to_string(),
+        runtime: Handle::current(),
+    });
+
+    tokio::task::block_in_place(|| service_dispatcher::start(SERVICE_NAME, ffi_service_main))
+        .context("Failed to start the service dispatcher, --service run is meant to be started by the service manager")
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        error!("Service failed: {:#}", e);
+    }
+}
+
+fn run_service() -> Result<()> {
+    let launch = LAUNCH.lock().unwrap().take().ok_or_else(|| anyhow!("Service started twice"))?;
+
+    let stop = CancellationToken::new();
+    let handler_stop = stop.clone();
+    let status = service_control_handler::register(SERVICE_NAME, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            handler_stop.cancel();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })
+    .context("Failed to register the service control handler")?;
+
+    let wait_hint = Duration::from_secs(launch.config.shutdown_timeout_secs + 5);
+    let report = |current_state, controls_accepted, exit_code| {
+        status.set_service_status(ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(exit_code),
+            checkpoint: 0,
+            wait_hint,
+            process_id: None,
+        })
+    };
+
+    report(ServiceState::Running, ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN, 0)
+        .context("Failed to report the service as running")?;
+    info!("Service {} started", SERVICE_NAME);
+
+    let result = launch.runtime.block_on(scheduler::run_scraper_until(
+        launch.config,
+        &launch.config_path,
+        false,
+        stop.cancelled_owned(),
+    ));
+    if let Err(e) = &result {
+        error!("Scraper stopped: {:#}", e);
+    }
+
+    report(ServiceState::Stopped, ServiceControlAccept::empty(), if result.is_ok() { 0 } else { 1 })
+        .context("Failed to report the service as stopped")?;
+
+    result
+}
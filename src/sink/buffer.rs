@@ -0,0 +1,93 @@
+use anyhow::{Context, Result};
+use influxdb2::models::{DataPoint, WriteDataPoint};
+use tracing::warn;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Append-only line protocol file holding points that could not be written
+/// to InfluxDB. The contents are replayed once the server is reachable again.
+pub struct WriteBuffer {
+    path: PathBuf,
+    max_size_bytes: u64,
+}
+
+impl WriteBuffer {
+    pub fn new(path: impl Into<PathBuf>, max_size_bytes: u64) -> Result<Self> {
+        let path = path.into();
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create buffer directory {}", parent.display()))?;
+        }
+
+        Ok(WriteBuffer { path, max_size_bytes })
+    }
+
+    /// Appends the points to the buffer. If the buffer would grow beyond
+    /// `max_size_bytes`, the oldest lines are discarded to make room.
+    pub fn push(&self, points: &[DataPoint]) -> Result<()> {
+        let mut lines = Vec::new();
+        for point in points {
+            point.write_data_point_to(&mut lines)
+                .context("Failed to serialize data point")?;
+        }
+
+        let current_size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+
+        if current_size + lines.len() as u64 <= self.max_size_bytes {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .with_context(|| format!("Failed to open buffer file {}", self.path.display()))?;
+
+            return file.write_all(&lines)
+                .with_context(|| format!("Failed to append to buffer file {}", self.path.display()));
+        }
+
+        let mut contents = self.load()?.unwrap_or_default().into_bytes();
+        contents.extend_from_slice(&lines);
+
+        let mut dropped = 0;
+        let mut start = 0;
+        while (contents.len() - start) as u64 > self.max_size_bytes {
+            match contents[start..].iter().position(|&b| b == b'\n') {
+                Some(pos) => {
+                    start += pos + 1;
+                    dropped += 1;
+                }
+                None => {
+                    start = contents.len();
+                }
+            }
+        }
+
+        warn!("Write buffer is full, dropped {} oldest points", dropped);
+
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, &contents[start..])
+            .with_context(|| format!("Failed to write buffer file {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to replace buffer file {}", self.path.display()))
+    }
+
+    /// Returns the buffered line protocol, or `None` if nothing is pending.
+    pub fn load(&self) -> Result<Option<String>> {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) if contents.is_empty() => Ok(None),
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read buffer file {}", self.path.display())),
+        }
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove buffer file {}", self.path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use super::Sink;
+use super::Sample;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+const HEADER: &str = "timestamp,area_code,location,free_spaces\n";
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct CsvConfig {
+    #[serde(default = "crate::config::default_true")]
+    pub enabled: bool,
+    /// Directory holding one `parking-YYYY-MM-DD.csv` file per local day.
+    pub directory: String,
+}
+
+/// Appends samples to daily CSV files. Dates and timestamps are in the
+/// configured local timezone so the files line up with the business day.
+pub struct CsvSink {
+    directory: PathBuf,
+    timezone: Tz,
+}
+
+impl CsvSink {
+    pub fn new(config: &CsvConfig, timezone: Tz) -> Result<Self> {
+        let directory = PathBuf::from(&config.directory);
+        fs::create_dir_all(&directory)
+            .with_context(|| format!("Failed to create CSV directory {}", directory.display()))?;
+
+        Ok(CsvSink { directory, timezone })
+    }
+}
+
+/// Quotes a field if it contains a delimiter, quote or line break.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[async_trait]
+impl Sink for CsvSink {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    async fn write_points(&mut self, samples: &[Sample]) -> Result<()> {
+        for sample in samples {
+            let local = sample.timestamp.with_timezone(&self.timezone);
+            let path = self.directory.join(format!("parking-{}.csv", local.format("%Y-%m-%d")));
+
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("Failed to open CSV file {}", path.display()))?;
+
+            let mut line = String::new();
+            if file.metadata().map(|m| m.len()).unwrap_or(0) == 0 {
+                line.push_str(HEADER);
+            }
+            line.push_str(&format!(
+                "{},{},{},{}\n",
+                local.format("%Y-%m-%d %H:%M:%S"),
+                sample.area_code,
+                escape(&sample.location),
+                sample.free_spaces,
+            ));
+
+            file.write_all(line.as_bytes())
+                .with_context(|| format!("Failed to append to CSV file {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
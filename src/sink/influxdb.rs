@@ -0,0 +1,348 @@
+use super::{Sample, Sink, WriteBuffer};
+use crate::config::{self, InfluxDbConfig, SchemaConfig};
+use crate::http;
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use influxdb2::models::{DataPoint, WriteDataPoint};
+use tokio::time;
+use tracing::{error, info, warn};
+
+/// Writes samples to InfluxDB, buffering them on disk while the server is
+/// unreachable if a write buffer is configured.
+pub struct InfluxSink {
+    config: InfluxDbConfig,
+    client: reqwest::Client,
+    buffer: Option<WriteBuffer>,
+    dry_run: bool,
+}
+
+impl InfluxSink {
+    pub fn new(config: &InfluxDbConfig, buffer: Option<WriteBuffer>, dry_run: bool) -> Result<Self> {
+        reqwest::Url::parse(&config.url)
+            .with_context(|| format!("Invalid InfluxDB URL: {}", config.url))?;
+
+        let builder = http::with_proxy(reqwest::Client::builder(), config.proxy_url.as_deref())?;
+        let client = http::with_tls(builder, config.tls.as_ref())?
+            .build()
+            .context("Failed to build InfluxDB HTTP client")?;
+
+        Ok(InfluxSink { config: config.clone(), client, buffer, dry_run })
+    }
+
+    /// A request to the 1.x `/write` or the 2.x `/api/v2/write` endpoint.
+    fn write_request(&self) -> reqwest::RequestBuilder {
+        let influxdb = &self.config;
+        let base = influxdb.url.trim_end_matches('/');
+
+        let request = match influxdb.version {
+            1 => {
+                let mut query = vec![("db", influxdb.database.as_str()), ("precision", "ns")];
+                if let Some(rp) = &influxdb.retention_policy {
+                    query.push(("rp", rp));
+                }
+
+                let request = self.client.post(format!("{}/write", base)).query(&query);
+                match &influxdb.username {
+                    Some(username) => request.basic_auth(username, influxdb.password.as_ref()),
+                    None => request,
+                }
+            }
+            _ => self.client.post(format!("{}/api/v2/write", base))
+                .query(&[("org", influxdb.org.as_str()), ("bucket", &influxdb.bucket), ("precision", "ns")])
+                .header(reqwest::header::AUTHORIZATION, format!("Token {}", influxdb.token)),
+        };
+
+        request.header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+    }
+
+    async fn write_line_protocol(&self, body: String) -> Result<()> {
+        self.write_request()
+            .body(body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("InfluxDB {}.x write failed", self.config.version))?;
+        Ok(())
+    }
+
+    /// Verifies that InfluxDB is reachable, that the bucket (or for 1.x the
+    /// database) exists and that the credentials may write to it, by
+    /// looking the bucket up and writing no points.
+    pub async fn check(&self) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        let influxdb = &self.config;
+        let target = match influxdb.version {
+            1 => format!("database {}", influxdb.database),
+            _ => format!("bucket {} of organization {}", influxdb.bucket, influxdb.org),
+        };
+
+        if influxdb.version != 1 {
+            self.check_bucket().await?;
+        }
+
+        let response = self.write_request()
+            .send()
+            .await
+            .with_context(|| format!("InfluxDB at {} is unreachable", influxdb.url))?;
+        match response.status() {
+            // An empty write may be rejected as such, but only after it has
+            // been authorized.
+            status if status.is_success() || status == reqwest::StatusCode::BAD_REQUEST => Ok(()),
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => Err(anyhow!(
+                "InfluxDB credentials may not write to {}, check the token's permissions",
+                target,
+            )),
+            reqwest::StatusCode::NOT_FOUND => Err(anyhow!("InfluxDB has no {}", target)),
+            status => Err(anyhow!("Test write to InfluxDB {} failed with {}", target, status)),
+        }
+    }
+
+    async fn check_bucket(&self) -> Result<()> {
+        if !self.bucket_exists().await? {
+            return Err(anyhow!(
+                "InfluxDB has no bucket {} in organization {} that the token can read, check influxdb.bucket",
+                self.config.bucket, self.config.org,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Looks the 2.x bucket up through the buckets API.
+    async fn bucket_exists(&self) -> Result<bool> {
+        let influxdb = &self.config;
+        let response = self.api_get("buckets", &[("org", &influxdb.org), ("name", &influxdb.bucket)]).await?;
+
+        let list: serde_json::Value = response.json()
+            .await
+            .context("Failed to parse the InfluxDB bucket list")?;
+        Ok(list["buckets"].as_array().is_some_and(|buckets| !buckets.is_empty()))
+    }
+
+    /// Creates the 2.x bucket unless it exists, keeping data for
+    /// `retention_secs` (forever if 0). Returns whether it was created.
+    pub async fn ensure_bucket(&self, retention_secs: u64) -> Result<bool> {
+        if self.bucket_exists().await? {
+            return Ok(false);
+        }
+
+        let influxdb = &self.config;
+        let orgs: serde_json::Value = self.api_get("orgs", &[("org", &influxdb.org)])
+            .await?
+            .json()
+            .await
+            .context("Failed to parse the InfluxDB organization list")?;
+        let org_id = orgs["orgs"][0]["id"].as_str()
+            .ok_or_else(|| anyhow!("InfluxDB has no organization {}, check influxdb.org", influxdb.org))?;
+
+        let retention_rules = match retention_secs {
+            0 => serde_json::json!([]),
+            secs => serde_json::json!([{ "type": "expire", "everySeconds": secs }]),
+        };
+        self.client.post(format!("{}/api/v2/buckets", influxdb.url.trim_end_matches('/')))
+            .header(reqwest::header::AUTHORIZATION, format!("Token {}", influxdb.token))
+            .json(&serde_json::json!({
+                "orgID": org_id,
+                "name": influxdb.bucket,
+                "retentionRules": retention_rules,
+            }))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("Failed to create InfluxDB bucket {}, the token may lack write:buckets", influxdb.bucket))?;
+        Ok(true)
+    }
+
+    /// A GET request to the 2.x management API, mapping the usual failures
+    /// to actionable errors.
+    async fn api_get(&self, path: &str, query: &[(&str, &str)]) -> Result<reqwest::Response> {
+        let influxdb = &self.config;
+        let response = self.client.get(format!("{}/api/v2/{}", influxdb.url.trim_end_matches('/'), path))
+            .query(query)
+            .header(reqwest::header::AUTHORIZATION, format!("Token {}", influxdb.token))
+            .send()
+            .await
+            .with_context(|| format!("InfluxDB at {} is unreachable", influxdb.url))?;
+
+        match response.status() {
+            reqwest::StatusCode::UNAUTHORIZED => Err(anyhow!("InfluxDB rejected the token, check influxdb.token")),
+            reqwest::StatusCode::NOT_FOUND => {
+                Err(anyhow!("InfluxDB has no organization {}, check influxdb.org", influxdb.org))
+            }
+            status if !status.is_success() => Err(anyhow!("InfluxDB /api/v2/{} failed with {}", path, status)),
+            _ => Ok(response),
+        }
+    }
+
+    /// Retries transient failures with backoff, see [`retryable`].
+    async fn write_with_retry(&self, body: String) -> Result<()> {
+        let retry = &self.config.retry;
+        let max_attempts = retry.max_attempts.max(1);
+        let mut attempt = 1;
+
+        loop {
+            match self.write_line_protocol(body.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < max_attempts && retryable(&e) => {
+                    let delay = retry.backoff(attempt);
+                    warn!("InfluxDB write attempt {}/{} failed: {:#}. Retrying in {:?}", attempt, max_attempts, e, delay);
+                    time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) if attempt > 1 => return Err(e.context(format!("Giving up after {} attempts", attempt))),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn replay_buffer(&self, buffer: &WriteBuffer) {
+        let pending = match buffer.load() {
+            Ok(Some(pending)) => pending,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Failed to read write buffer: {:#}", e);
+                return;
+            }
+        };
+
+        let count = pending.lines().count();
+        match self.write_line_protocol(pending).await {
+            Ok(_) => {
+                info!("Replayed {} buffered points to InfluxDB", count);
+                if let Err(e) = buffer.clear() {
+                    error!("Failed to clear write buffer: {:#}", e);
+                }
+            }
+            Err(e) => warn!("InfluxDB still unavailable, keeping {} buffered points: {:#}", count, e),
+        }
+    }
+
+    pub(super) fn data_point(&self, sample: &Sample) -> DataPoint {
+        sample.to_data_point(&self.config.schema)
+    }
+
+    pub(super) async fn write_data_points(&self, data_points: Vec<DataPoint>) -> Result<()> {
+        if self.dry_run {
+            let mut stdout = std::io::stdout().lock();
+            for point in &data_points {
+                point.write_data_point_to(&mut stdout).ok();
+            }
+            return Ok(());
+        }
+
+        let mut lines = Vec::new();
+        for point in &data_points {
+            point.write_data_point_to(&mut lines)
+                .context("Failed to serialize data point")?;
+        }
+        let body = String::from_utf8(lines).context("Data point is not valid UTF-8")?;
+
+        let Some(buffer) = &self.buffer else {
+            return self.write_with_retry(body).await;
+        };
+
+        self.replay_buffer(buffer).await;
+
+        // Points InfluxDB rejected would be rejected again on replay.
+        let result = self.write_with_retry(body).await;
+        if let Err(e) = &result
+            && retryable(e)
+        {
+            match buffer.push(&data_points) {
+                Ok(_) => info!("Buffered {} points for later replay", data_points.len()),
+                Err(e) => error!("Failed to buffer points: {:#}", e),
+            }
+        }
+
+        result
+    }
+}
+
+/// Whether a write may succeed if tried again: timeouts, connection errors
+/// and 5xx or 429 responses, as opposed to e.g. 401 for a bad token, 404 for
+/// a missing bucket or 400 for rejected points.
+fn retryable(error: &anyhow::Error) -> bool {
+    let Some(error) = error.chain().find_map(|e| e.downcast_ref::<reqwest::Error>()) else {
+        return false;
+    };
+
+    match error.status() {
+        Some(status) => status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+        None => !error.is_builder(),
+    }
+}
+
+#[async_trait]
+impl Sink for InfluxSink {
+    fn name(&self) -> &str {
+        "influxdb"
+    }
+
+    async fn write_points(&mut self, samples: &[Sample]) -> Result<()> {
+        let data_points = samples.iter().map(|sample| self.data_point(sample)).collect();
+        self.write_data_points(data_points).await
+    }
+}
+
+impl Sample {
+    pub fn to_data_point(&self, schema: &SchemaConfig) -> DataPoint {
+        let name = |key| schema.name(key).to_string();
+        let mut builder = DataPoint::builder(&schema.measurement)
+            .tag(name("area_code"), self.area_code.to_string())
+            .tag(name("location"), &self.location);
+
+        for (key, value) in &self.tags {
+            builder = builder.tag(name(key), value);
+        }
+
+        if let Some(pct) = self.occupancy_pct {
+            builder = builder.field(name("occupancy_pct"), pct);
+        }
+
+        if let Some(total) = self.total_spaces {
+            builder = builder
+                .field(name("total_spaces"), total)
+                .field(name("occupied_spaces"), config::occupied_spaces(total, self.free_spaces));
+        }
+
+        if let Some(delta) = self.delta_spaces {
+            builder = builder.field(name("delta_spaces"), delta);
+        }
+
+        if let Some(predicted) = self.predicted_free_spaces {
+            builder = builder.field(name("predicted_free_spaces"), predicted);
+        }
+
+        if let Some(smoothed) = self.free_spaces_smoothed {
+            builder = builder.field(name("free_spaces_smoothed"), smoothed);
+        }
+
+        if let Some(until_full) = self.until_full {
+            builder = builder
+                .field(name("seconds_until_full"), until_full.secs)
+                .field(name("until_full_valid"), until_full.valid);
+        }
+
+        if let Some(window) = self.window {
+            builder = builder
+                .field(name("free_spaces_min"), window.min)
+                .field(name("free_spaces_max"), window.max)
+                .field(name("free_spaces_mean"), window.mean);
+        }
+
+        if let Some(lag) = self.data_lag_secs {
+            builder = builder.field(name("data_lag_secs"), lag);
+        }
+
+        for (key, value) in &self.fields {
+            builder = builder.field(name(key), *value);
+        }
+
+        builder
+            .field(name("free_spaces"), self.free_spaces)
+            .timestamp(self.timestamp.timestamp_nanos_opt().unwrap())
+            .build()
+            .unwrap()
+    }
+}
@@ -0,0 +1,191 @@
+mod buffer;
+mod csv;
+mod influxdb;
+mod mqtt;
+mod parquet;
+mod sqlite;
+mod writer;
+
+pub use buffer::WriteBuffer;
+pub use csv::CsvConfig;
+pub use influxdb::InfluxSink;
+pub use mqtt::MqttConfig;
+pub use parquet::ParquetConfig;
+pub use sqlite::{SqliteConfig, SqliteSink};
+pub use writer::Writer;
+
+use crate::config::{AppConfig, AreaConfig, SourceConfig};
+use crate::metrics::Metrics;
+use crate::source::AreaData;
+use crate::sampling::WindowStats;
+use crate::trend::UntilFull;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tracing::{error, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// A single observation of one area, as handed to InfluxDB and the other
+/// sinks.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub source: String,
+    pub area_code: i32,
+    pub location: String,
+    pub free_spaces: i64,
+    pub occupancy_pct: Option<f64>,
+    /// Capacity of the area, if configured.
+    pub total_spaces: Option<i64>,
+    pub delta_spaces: Option<f64>,
+    /// Average free spaces seen in the coming hour of the week, one hour
+    /// ahead of `timestamp`.
+    pub predicted_free_spaces: Option<f64>,
+    /// Moving average of `free_spaces`, for areas with a
+    /// `filter.smoothing_alpha`.
+    pub free_spaces_smoothed: Option<f64>,
+    /// Estimated time until the area is full, for sources with
+    /// `time_until_full`.
+    pub until_full: Option<UntilFull>,
+    /// Spread of the values fetched since the previous write, for sources
+    /// with `samples_per_interval`.
+    pub window: Option<WindowStats>,
+    /// Seconds between the API generating the data and the scrape, for
+    /// sources with `api_timestamp`.
+    pub data_lag_secs: Option<f64>,
+    /// Values picked from the response by the source's `fields`.
+    pub fields: BTreeMap<String, f64>,
+    /// Extra tags from the source's configuration, plus `suspect=true` for
+    /// values flagged as implausible.
+    pub tags: HashMap<String, String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Builds the sample for one area of a freshly scraped or cached response.
+pub fn create_sample(
+    area: &AreaData,
+    source: &SourceConfig,
+    config: &AppConfig,
+    delta_per_minute: Option<f64>,
+) -> Sample {
+    let area_config = source.area(&config.areas, area.area_code);
+    let occupancy_pct = area_config.and_then(|config| config.occupancy_pct(area.area_free_space_num));
+
+    let mut tags = config.tags_for(source);
+    if let Some(area_config) = area_config {
+        tags.extend(area_config.tags.iter().map(|(key, value)| (key.clone(), value.clone())));
+    }
+
+    Sample {
+        source: source.name.clone(),
+        area_code: area.area_code,
+        location: source.location_for(&config.areas, area.area_code).to_string(),
+        free_spaces: area.area_free_space_num,
+        occupancy_pct,
+        total_spaces: area_config.and_then(AreaConfig::capacity),
+        delta_spaces: delta_per_minute,
+        predicted_free_spaces: None,
+        free_spaces_smoothed: None,
+        until_full: None,
+        window: None,
+        data_lag_secs: None,
+        fields: area.fields.clone(),
+        tags,
+        timestamp: Utc::now(),
+    }
+}
+
+/// Batches queued per sink before new ones are dropped.
+const QUEUE_CAPACITY: usize = 64;
+
+/// A destination for scraped samples. InfluxDB is the primary sink whose
+/// result is reported back to the source; failures of the other sinks are
+/// only logged and never fail the scrape cycle.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn write_points(&mut self, samples: &[Sample]) -> Result<()>;
+
+    /// Flushes anything still queued before the sink is dropped.
+    async fn close(&mut self) {}
+}
+
+/// Runs a sink on its own task behind a bounded queue, so a slow or
+/// unreachable sink only ever delays itself.
+pub struct SinkHandle {
+    name: String,
+    tx: mpsc::Sender<Arc<[Sample]>>,
+    task: JoinHandle<()>,
+    metrics: Arc<Metrics>,
+}
+
+impl SinkHandle {
+    pub fn spawn(mut sink: Box<dyn Sink>, metrics: Arc<Metrics>) -> Self {
+        let name = sink.name().to_string();
+        let (tx, mut rx) = mpsc::channel::<Arc<[Sample]>>(QUEUE_CAPACITY);
+
+        let task_metrics = metrics.clone();
+        let task = tokio::spawn(async move {
+            while let Some(samples) = rx.recv().await {
+                let result = sink.write_points(&samples).await;
+                if let Err(e) = &result {
+                    error!("Failed to write to {} sink: {:#}", sink.name(), e);
+                }
+                task_metrics.record_sink_write(sink.name(), samples.len(), result.is_ok());
+            }
+            sink.close().await;
+        });
+
+        SinkHandle { name, tx, task, metrics }
+    }
+
+    /// Queues the samples without waiting. If the sink has fallen too far
+    /// behind, the samples are dropped for this sink only.
+    pub fn send(&self, samples: Arc<[Sample]>) {
+        if let Err(mpsc::error::TrySendError::Full(samples)) = self.tx.try_send(samples) {
+            warn!("{} sink is falling behind, dropped {} samples", self.name, samples.len());
+            self.metrics.record_sink_dropped(&self.name, samples.len());
+        }
+    }
+
+    /// Writes everything still queued, then closes the sink.
+    pub async fn close(self) {
+        drop(self.tx);
+        let _ = self.task.await;
+    }
+}
+
+/// Builds every sink enabled in the configuration, InfluxDB excluded.
+pub fn build(config: &AppConfig) -> Result<Vec<Box<dyn Sink>>> {
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+
+    if let Some(mqtt) = config.mqtt.as_ref().filter(|c| c.enabled) {
+        sinks.push(Box::new(mqtt::MqttSink::new(mqtt)?));
+    }
+
+    if let Some(csv) = config.csv.as_ref().filter(|c| c.enabled) {
+        sinks.push(Box::new(csv::CsvSink::new(csv, config.timezone)?));
+    }
+
+    if let Some(parquet) = config.parquet.as_ref().filter(|c| c.enabled) {
+        sinks.push(Box::new(parquet::ParquetSink::new(parquet)?));
+    }
+
+    if let Some(sqlite) = config.sqlite.as_ref().filter(|c| c.enabled) {
+        sinks.push(Box::new(SqliteSink::new(sqlite)?));
+    }
+
+    Ok(sinks)
+}
+
+/// Whether the sinks need to be rebuilt after a configuration reload.
+pub fn changed(old: &AppConfig, new: &AppConfig) -> bool {
+    old.mqtt != new.mqtt
+        || old.csv != new.csv
+        || old.parquet != new.parquet
+        || old.sqlite != new.sqlite
+        || old.timezone != new.timezone
+}
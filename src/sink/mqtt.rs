@@ -0,0 +1,264 @@
+use super::Sink;
+use super::Sample;
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use tracing::{info, warn};
+use rumqttc::{AsyncClient, ConnectionError, Event, EventLoop, LastWill, MqttOptions, Outgoing, Packet, QoS};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// Messages queued while the broker is unreachable. Once full, new samples
+/// are dropped rather than stalling the writer.
+const QUEUE_CAPACITY: usize = 100;
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct MqttConfig {
+    #[serde(default = "crate::config::default_true")]
+    pub enabled: bool,
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Topic for each sample, with `{source}`, `{area_code}` and `{location}`
+    /// placeholders. The payload is the number of free spaces.
+    #[serde(default = "default_topic")]
+    pub topic: String,
+    #[serde(default = "default_qos")]
+    pub qos: u8,
+    #[serde(default)]
+    pub retain: bool,
+    #[serde(default = "default_keep_alive_secs")]
+    pub keep_alive_secs: u64,
+    /// Announces every area to Home Assistant through MQTT discovery.
+    pub home_assistant: Option<HomeAssistantConfig>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct HomeAssistantConfig {
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+    /// Groups the sensors under one device and names the availability topic
+    /// `<node_id>/status`.
+    #[serde(default = "default_node_id")]
+    pub node_id: String,
+}
+
+fn default_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+fn default_node_id() -> String {
+    "msparking".to_string()
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_client_id() -> String {
+    "msparking".to_string()
+}
+
+fn default_topic() -> String {
+    "parking/{area_code}/free".to_string()
+}
+
+fn default_qos() -> u8 {
+    1
+}
+
+fn default_keep_alive_secs() -> u64 {
+    30
+}
+
+pub struct MqttSink {
+    client: AsyncClient,
+    topic: String,
+    qos: QoS,
+    retain: bool,
+    discovery: Option<Discovery>,
+    eventloop: JoinHandle<()>,
+}
+
+/// Home Assistant discovery state: which areas have been announced so far.
+struct Discovery {
+    config: HomeAssistantConfig,
+    announced: HashSet<(String, i32)>,
+}
+
+impl Discovery {
+    fn availability_topic(&self) -> String {
+        format!("{}/status", self.config.node_id)
+    }
+
+    fn config_topic(&self, sample: &Sample) -> String {
+        format!(
+            "{}/sensor/{}/{}_{}/config",
+            self.config.discovery_prefix,
+            self.config.node_id,
+            object_id(&sample.source),
+            sample.area_code,
+        )
+    }
+
+    fn config_payload(&self, sample: &Sample, state_topic: &str) -> String {
+        let node_id = &self.config.node_id;
+        serde_json::json!({
+            "name": format!("{} free spaces", sample.location),
+            "unique_id": format!("{}_{}_{}", node_id, object_id(&sample.source), sample.area_code),
+            "state_topic": state_topic,
+            "availability_topic": self.availability_topic(),
+            "unit_of_measurement": "spaces",
+            "state_class": "measurement",
+            "icon": "mdi:parking",
+            "device": {
+                "identifiers": [node_id],
+                "name": "msparking",
+            },
+        })
+        .to_string()
+    }
+}
+
+/// Reduces a name to the characters Home Assistant allows in object ids.
+fn object_id(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect()
+}
+
+impl MqttSink {
+    pub fn new(config: &MqttConfig) -> Result<Self> {
+        let qos = match config.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            2 => QoS::ExactlyOnce,
+            other => return Err(anyhow!("Invalid MQTT qos {}, expected 0, 1 or 2", other)),
+        };
+
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(config.keep_alive_secs.max(5)));
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            options.set_credentials(username, password);
+        }
+
+        let discovery = config.home_assistant.clone().map(|config| Discovery {
+            config,
+            announced: HashSet::new(),
+        });
+        // The broker marks the sensors unavailable if the connection drops.
+        let availability = discovery.as_ref().map(Discovery::availability_topic);
+        if let Some(topic) = &availability {
+            options.set_last_will(LastWill::new(topic, "offline", QoS::AtLeastOnce, true));
+        }
+
+        let (client, eventloop) = AsyncClient::new(options, QUEUE_CAPACITY);
+        let broker = format!("{}:{}", config.host, config.port);
+        let online = availability.map(|topic| (client.clone(), topic));
+
+        Ok(MqttSink {
+            client,
+            topic: config.topic.clone(),
+            qos,
+            retain: config.retain,
+            discovery,
+            eventloop: tokio::spawn(drive(eventloop, broker, online)),
+        })
+    }
+
+    fn topic_for(&self, sample: &Sample) -> String {
+        self.topic
+            .replace("{source}", &sample.source)
+            .replace("{area_code}", &sample.area_code.to_string())
+            .replace("{location}", &sample.location)
+    }
+}
+
+/// Polls the connection until the client disconnects. rumqttc reconnects on
+/// the next poll after an error, so failures only need a backoff here. If
+/// `online` is set, its topic is marked online after every (re)connect.
+async fn drive(mut eventloop: EventLoop, broker: String, online: Option<(AsyncClient, String)>) {
+    let mut failures = 0;
+
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                if failures > 0 {
+                    info!("Reconnected to MQTT broker {}", broker);
+                } else {
+                    info!("Connected to MQTT broker {}", broker);
+                }
+                failures = 0;
+
+                if let Some((client, topic)) = &online
+                    && let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, true, "online")
+                {
+                    warn!("Failed to publish MQTT availability: {}", e);
+                }
+            }
+            Ok(Event::Outgoing(Outgoing::Disconnect)) | Err(ConnectionError::RequestsDone) => return,
+            Ok(_) => {}
+            Err(e) => {
+                failures += 1;
+                let delay = Duration::from_secs(1 << failures.min(5)).min(MAX_RECONNECT_DELAY);
+                warn!("MQTT connection to {} failed: {}. Retrying in {:?}", broker, e, delay);
+                time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for MqttSink {
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    async fn write_points(&mut self, samples: &[Sample]) -> Result<()> {
+        for sample in samples {
+            let topic = self.topic_for(sample);
+
+            if let Some(discovery) = &mut self.discovery {
+                let key = (sample.source.clone(), sample.area_code);
+                if !discovery.announced.contains(&key) {
+                    // Retained so Home Assistant picks the sensor up after a restart.
+                    self.client
+                        .try_publish(
+                            discovery.config_topic(sample),
+                            QoS::AtLeastOnce,
+                            true,
+                            discovery.config_payload(sample, &topic),
+                        )
+                        .context("MQTT queue is full, broker unreachable?")?;
+                    discovery.announced.insert(key);
+                }
+            }
+
+            self.client
+                .try_publish(topic, self.qos, self.retain, sample.free_spaces.to_string())
+                .context("MQTT queue is full, broker unreachable?")?;
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) {
+        if let Some(discovery) = &self.discovery {
+            let _ = self.client.try_publish(discovery.availability_topic(), QoS::AtLeastOnce, true, "offline");
+        }
+
+        if self.client.try_disconnect().is_ok()
+            && time::timeout(Duration::from_secs(5), &mut self.eventloop).await.is_ok()
+        {
+            return;
+        }
+        self.eventloop.abort();
+    }
+}
@@ -0,0 +1,145 @@
+use super::Sink;
+use super::Sample;
+use anyhow::{Context, Result};
+use arrow_array::{ArrayRef, Float64Array, Int32Array, Int64Array, RecordBatch, StringArray, TimestampNanosecondArray};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use async_trait::async_trait;
+use chrono::{DateTime, Timelike, Utc};
+use tracing::{error, info};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::Deserialize;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct ParquetConfig {
+    #[serde(default = "crate::config::default_true")]
+    pub enabled: bool,
+    /// Directory holding one `parking-YYYY-MM-DDTHH.parquet` file per UTC
+    /// hour.
+    pub directory: String,
+}
+
+/// Collects the samples of the current UTC hour in memory and writes them
+/// as one Parquet file once the hour is over or the sink is closed.
+pub struct ParquetSink {
+    directory: PathBuf,
+    hour: Option<DateTime<Utc>>,
+    pending: Vec<Sample>,
+}
+
+impl ParquetSink {
+    pub fn new(config: &ParquetConfig) -> Result<Self> {
+        let directory = PathBuf::from(&config.directory);
+        fs::create_dir_all(&directory)
+            .with_context(|| format!("Failed to create Parquet directory {}", directory.display()))?;
+
+        Ok(ParquetSink { directory, hour: None, pending: Vec::new() })
+    }
+
+    /// Writes the pending samples. A restart within the same hour produces
+    /// a second file with a numbered suffix instead of overwriting the first.
+    fn flush(&mut self) -> Result<()> {
+        let Some(hour) = self.hour else {
+            return Ok(());
+        };
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let stem = format!("parking-{}", hour.format("%Y-%m-%dT%H"));
+        let mut path = self.directory.join(format!("{}.parquet", stem));
+        let mut n = 1;
+        while path.exists() {
+            path = self.directory.join(format!("{}.{}.parquet", stem, n));
+            n += 1;
+        }
+
+        let batch = record_batch(&self.pending)?;
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create Parquet file {}", path.display()))?;
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(props))?;
+        writer.write(&batch)?;
+        writer.close()
+            .with_context(|| format!("Failed to write Parquet file {}", path.display()))?;
+
+        info!("Wrote {} samples to {}", self.pending.len(), path.display());
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+fn record_batch(samples: &[Sample]) -> Result<RecordBatch> {
+    let schema = Schema::new(vec![
+        Field::new("timestamp", DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())), false),
+        Field::new("source", DataType::Utf8, false),
+        Field::new("area_code", DataType::Int32, false),
+        Field::new("location", DataType::Utf8, false),
+        Field::new("free_spaces", DataType::Int64, false),
+        Field::new("occupancy_pct", DataType::Float64, true),
+        Field::new("delta_spaces", DataType::Float64, true),
+        Field::new("tags", DataType::Utf8, false),
+    ]);
+
+    let tags = samples.iter()
+        .map(|s| serde_json::to_string(&s.tags))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(
+            TimestampNanosecondArray::from_iter_values(
+                samples.iter().map(|s| s.timestamp.timestamp_nanos_opt().unwrap_or_default()),
+            )
+            .with_timezone("UTC"),
+        ),
+        Arc::new(StringArray::from_iter_values(samples.iter().map(|s| s.source.as_str()))),
+        Arc::new(Int32Array::from_iter_values(samples.iter().map(|s| s.area_code))),
+        Arc::new(StringArray::from_iter_values(samples.iter().map(|s| s.location.as_str()))),
+        Arc::new(Int64Array::from_iter_values(samples.iter().map(|s| s.free_spaces))),
+        Arc::new(Float64Array::from_iter(samples.iter().map(|s| s.occupancy_pct))),
+        Arc::new(Float64Array::from_iter(samples.iter().map(|s| s.delta_spaces))),
+        Arc::new(StringArray::from_iter_values(tags)),
+    ];
+
+    RecordBatch::try_new(Arc::new(schema), columns).context("Failed to build Parquet record batch")
+}
+
+fn hour_of(timestamp: DateTime<Utc>) -> DateTime<Utc> {
+    timestamp
+        .with_minute(0)
+        .and_then(|t| t.with_second(0))
+        .and_then(|t| t.with_nanosecond(0))
+        .unwrap_or(timestamp)
+}
+
+#[async_trait]
+impl Sink for ParquetSink {
+    fn name(&self) -> &str {
+        "parquet"
+    }
+
+    async fn write_points(&mut self, samples: &[Sample]) -> Result<()> {
+        for sample in samples {
+            let hour = hour_of(sample.timestamp);
+            if self.hour != Some(hour) {
+                // Keep the samples on failure; they are retried with the next flush.
+                self.flush()?;
+                self.hour = Some(hour);
+            }
+            self.pending.push(sample.clone());
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) {
+        if let Err(e) = self.flush() {
+            error!("Failed to flush Parquet sink: {:#}", e);
+        }
+    }
+}
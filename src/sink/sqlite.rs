@@ -0,0 +1,252 @@
+use super::Sink;
+use super::Sample;
+use crate::sampling::WindowStats;
+use crate::trend::UntilFull;
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use chrono::DateTime;
+use tracing::info;
+use rusqlite::{Connection, params};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Rows pushed upstream per request when exporting.
+const EXPORT_BATCH_SIZE: usize = 5000;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS samples (
+        id INTEGER PRIMARY KEY,
+        timestamp_ns INTEGER NOT NULL,
+        source TEXT NOT NULL,
+        area_code INTEGER NOT NULL,
+        location TEXT NOT NULL,
+        free_spaces INTEGER NOT NULL,
+        occupancy_pct REAL,
+        delta_spaces REAL,
+        tags TEXT NOT NULL,
+        exported INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS samples_pending ON samples (exported, id);
+";
+
+/// Columns added after the first schema, added to older databases when they
+/// are opened.
+const ADDED_COLUMNS: [(&str, &str); 10] = [
+    ("total_spaces", "INTEGER"),
+    ("predicted_free_spaces", "REAL"),
+    ("free_spaces_smoothed", "REAL"),
+    ("seconds_until_full", "REAL"),
+    ("until_full_valid", "INTEGER"),
+    ("free_spaces_min", "INTEGER"),
+    ("free_spaces_max", "INTEGER"),
+    ("free_spaces_mean", "REAL"),
+    ("data_lag_secs", "REAL"),
+    ("fields", "TEXT NOT NULL DEFAULT '{}'"),
+];
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct SqliteConfig {
+    #[serde(default = "crate::config::default_true")]
+    pub enabled: bool,
+    pub path: String,
+}
+
+/// Stores every sample in a local SQLite database, so edge deployments
+/// without access to InfluxDB can push the history upstream later.
+pub struct SqliteSink {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteSink {
+    pub fn new(config: &SqliteConfig) -> Result<Self> {
+        let path = Path::new(&config.path);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create SQLite directory {}", parent.display()))?;
+        }
+
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open SQLite database {}", path.display()))?;
+        conn.execute_batch(SCHEMA)
+            .context("Failed to create SQLite schema")?;
+        let columns = conn.prepare("SELECT name FROM pragma_table_info('samples')")
+            .and_then(|mut stmt| stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>())
+            .context("Failed to read the SQLite schema")?;
+        for (name, kind) in ADDED_COLUMNS {
+            if !columns.iter().any(|column| column == name) {
+                conn.execute_batch(&format!("ALTER TABLE samples ADD COLUMN {} {}", name, kind))
+                    .with_context(|| format!("Failed to add column {} to the SQLite schema", name))?;
+            }
+        }
+
+        Ok(SqliteSink { conn: Mutex::new(conn) })
+    }
+
+    /// Writes every sample not yet exported to `target` in batches, marking
+    /// each batch once it has been accepted. With `dry_run` nothing is
+    /// marked, so the export can be repeated for real afterwards.
+    pub async fn export(&self, target: &mut dyn Sink, dry_run: bool) -> Result<usize> {
+        let mut after = 0;
+        let mut exported = 0;
+
+        loop {
+            let rows = self.pending(after)?;
+            let Some(&(last_id, _)) = rows.last() else {
+                return Ok(exported);
+            };
+
+            let samples: Vec<Sample> = rows.into_iter().map(|(_, sample)| sample).collect();
+            target.write_points(&samples)
+                .await
+                .with_context(|| format!("Failed to export samples to {}", target.name()))?;
+
+            if !dry_run {
+                self.lock()?
+                    .execute("UPDATE samples SET exported = 1 WHERE id > ?1 AND id <= ?2", params![after, last_id])
+                    .context("Failed to mark exported samples")?;
+            }
+
+            exported += samples.len();
+            after = last_id;
+            info!("Exported {} samples to {}", exported, target.name());
+        }
+    }
+
+    fn pending(&self, after: i64) -> Result<Vec<(i64, Sample)>> {
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, timestamp_ns, source, area_code, location, free_spaces, occupancy_pct, delta_spaces, tags,
+                    total_spaces, predicted_free_spaces, free_spaces_smoothed, seconds_until_full, until_full_valid,
+                    free_spaces_min, free_spaces_max, free_spaces_mean, data_lag_secs, fields
+             FROM samples WHERE exported = 0 AND id > ?1 ORDER BY id LIMIT ?2",
+        )?;
+
+        let rows = stmt.query_map(params![after, EXPORT_BATCH_SIZE], |row| {
+            let until_full = match (row.get::<_, Option<f64>>(12)?, row.get::<_, Option<bool>>(13)?) {
+                (Some(secs), Some(valid)) => Some(UntilFull { secs, valid }),
+                _ => None,
+            };
+            let window = match (row.get::<_, Option<i64>>(14)?, row.get::<_, Option<i64>>(15)?, row.get::<_, Option<f64>>(16)?) {
+                (Some(min), Some(max), Some(mean)) => Some(WindowStats { min, max, mean }),
+                _ => None,
+            };
+            let sample = Sample {
+                source: row.get(2)?,
+                area_code: row.get(3)?,
+                location: row.get(4)?,
+                free_spaces: row.get(5)?,
+                occupancy_pct: row.get(6)?,
+                total_spaces: row.get(9)?,
+                delta_spaces: row.get(7)?,
+                predicted_free_spaces: row.get(10)?,
+                free_spaces_smoothed: row.get(11)?,
+                window,
+                until_full,
+                data_lag_secs: row.get(17)?,
+                fields: BTreeMap::new(),
+                tags: HashMap::new(),
+                timestamp: DateTime::from_timestamp_nanos(row.get(1)?),
+            };
+            Ok((row.get::<_, i64>(0)?, sample, row.get::<_, String>(8)?, row.get::<_, String>(18)?))
+        })?;
+
+        let mut pending = Vec::new();
+        for row in rows {
+            let (id, mut sample, tags, fields) = row.context("Failed to read SQLite row")?;
+            sample.tags = serde_json::from_str(&tags)
+                .with_context(|| format!("Invalid tags in SQLite row {}", id))?;
+            sample.fields = serde_json::from_str(&fields)
+                .with_context(|| format!("Invalid fields in SQLite row {}", id))?;
+            pending.push((id, sample));
+        }
+        Ok(pending)
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.conn.lock().map_err(|_| anyhow!("SQLite connection poisoned"))
+    }
+}
+
+#[async_trait]
+impl Sink for SqliteSink {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    async fn write_points(&mut self, samples: &[Sample]) -> Result<()> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO samples (timestamp_ns, source, area_code, location, free_spaces, occupancy_pct, delta_spaces, tags,
+                                      total_spaces, predicted_free_spaces, free_spaces_smoothed, seconds_until_full, until_full_valid,
+                                      free_spaces_min, free_spaces_max, free_spaces_mean, data_lag_secs, fields)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            )?;
+            for sample in samples {
+                stmt.execute(params![
+                    sample.timestamp.timestamp_nanos_opt(),
+                    sample.source,
+                    sample.area_code,
+                    sample.location,
+                    sample.free_spaces,
+                    sample.occupancy_pct,
+                    sample.delta_spaces,
+                    serde_json::to_string(&sample.tags)?,
+                    sample.total_spaces,
+                    sample.predicted_free_spaces,
+                    sample.free_spaces_smoothed,
+                    sample.until_full.map(|until_full| until_full.secs),
+                    sample.until_full.map(|until_full| until_full.valid),
+                    sample.window.map(|window| window.min),
+                    sample.window.map(|window| window.max),
+                    sample.window.map(|window| window.mean),
+                    sample.data_lag_secs,
+                    serde_json::to_string(&sample.fields)?,
+                ])?;
+            }
+        }
+        tx.commit().context("Failed to insert samples into SQLite")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[tokio::test]
+    async fn keeps_every_field_until_exported() {
+        let path = std::env::temp_dir().join(format!("msparking-sqlite-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut sink = SqliteSink::new(&SqliteConfig { enabled: true, path: path.display().to_string() }).unwrap();
+
+        let sample = Sample {
+            source: "default".to_string(),
+            area_code: 12,
+            location: "A".to_string(),
+            free_spaces: 40,
+            occupancy_pct: Some(60.0),
+            total_spaces: Some(100),
+            delta_spaces: Some(-1.5),
+            predicted_free_spaces: Some(35.0),
+            free_spaces_smoothed: Some(41.5),
+            until_full: Some(UntilFull { secs: 1200.0, valid: true }),
+            window: Some(WindowStats { min: 38, max: 44, mean: 40.5 }),
+            data_lag_secs: Some(12.0),
+            fields: BTreeMap::from([("temperature".to_string(), 21.5)]),
+            tags: HashMap::from([("city".to_string(), "suzhou".to_string())]),
+            timestamp: Utc.with_ymd_and_hms(2026, 10, 14, 8, 0, 0).unwrap(),
+        };
+        sink.write_points(std::slice::from_ref(&sample)).await.unwrap();
+
+        let pending = sink.pending(0).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let [(_, read)] = pending.as_slice() else {
+            panic!("expected one pending sample, found {}", pending.len());
+        };
+        assert_eq!(format!("{:?}", read), format!("{:?}", sample));
+    }
+}
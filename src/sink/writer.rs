@@ -0,0 +1,433 @@
+use super::influxdb::InfluxSink;
+use super::{Sample, SinkHandle, WriteBuffer};
+use crate::config::{AppConfig, BufferConfig, QueuePolicy};
+use crate::metrics::Metrics;
+use anyhow::{Result, anyhow};
+use influxdb2::models::DataPoint;
+use tracing::{error, info, warn};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant};
+
+/// Capacity of the write queue unless `write_queue` is configured.
+const QUEUE_CAPACITY: usize = 16;
+
+struct Batch {
+    samples: Vec<Sample>,
+    /// Extra points that only go to InfluxDB, such as agent telemetry.
+    points: Vec<DataPoint>,
+    /// `None` if the sender did not wait for the write.
+    reply: Option<oneshot::Sender<Result<()>>>,
+}
+
+/// Batches waiting for the writer task.
+struct Queue {
+    state: Mutex<QueueState>,
+    capacity: usize,
+    policy: QueuePolicy,
+    /// Whether senders return once their batch is queued.
+    detached: bool,
+    pushed: Notify,
+    popped: Notify,
+    metrics: Arc<Metrics>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    batches: VecDeque<Batch>,
+    /// Set once every [`Writer`] is gone.
+    closed: bool,
+}
+
+impl Queue {
+    async fn push(&self, batch: Batch) {
+        loop {
+            let popped = self.popped.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.batches.len() >= self.capacity
+                    && self.policy == QueuePolicy::DropOldest
+                    && let Some(oldest) = state.batches.pop_front()
+                {
+                    warn!("Write queue is full, dropped the oldest batch of {} samples", oldest.samples.len());
+                    self.metrics.record_write_queue_dropped();
+                    if let Some(reply) = oldest.reply {
+                        let _ = reply.send(Err(anyhow!("Dropped from the full write queue")));
+                    }
+                }
+                if state.batches.len() < self.capacity {
+                    state.batches.push_back(batch);
+                    self.metrics.set_write_queue_depth(state.batches.len());
+                    self.pushed.notify_one();
+                    return;
+                }
+            }
+            popped.await;
+        }
+    }
+
+    /// `None` once the queue is closed and empty.
+    async fn pop(&self) -> Option<Batch> {
+        loop {
+            let pushed = self.pushed.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(batch) = state.batches.pop_front() {
+                    self.metrics.set_write_queue_depth(state.batches.len());
+                    self.popped.notify_one();
+                    return Some(batch);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            pushed.await;
+        }
+    }
+}
+
+/// Closes the queue when the last [`Writer`] is dropped.
+struct Sender(Arc<Queue>);
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().closed = true;
+        self.0.pushed.notify_one();
+    }
+}
+
+/// Handle to the single task that owns the InfluxDB client and fans batches
+/// out to the other sinks. Every source task sends its points through a
+/// clone of this handle, so writes are serialized and the write buffer has
+/// exactly one owner.
+#[derive(Clone)]
+pub struct Writer {
+    sender: Arc<Sender>,
+}
+
+impl Writer {
+    pub fn spawn(
+        config: watch::Receiver<Arc<AppConfig>>,
+        metrics: Arc<Metrics>,
+        dry_run: bool,
+    ) -> Result<(Writer, JoinHandle<()>)> {
+        let write_queue = config.borrow().write_queue.as_ref().map(|queue| (queue.capacity.max(1), queue.policy));
+        let (capacity, policy) = write_queue.unwrap_or((QUEUE_CAPACITY, QueuePolicy::Block));
+        let queue = Arc::new(Queue {
+            state: Mutex::default(),
+            capacity,
+            policy,
+            detached: write_queue.is_some(),
+            pushed: Notify::new(),
+            popped: Notify::new(),
+            metrics: metrics.clone(),
+        });
+
+        let task = WriterTask::new(config, metrics, dry_run)?;
+        let handle = tokio::spawn(task.run(queue.clone()));
+        Ok((Writer { sender: Arc::new(Sender(queue)) }, handle))
+    }
+
+    /// Writes the samples and waits for InfluxDB to acknowledge them. Other
+    /// sinks receive them in the background without affecting the result.
+    /// With a `write_queue`, returns as soon as they are queued.
+    pub async fn write(&self, samples: Vec<Sample>) -> Result<()> {
+        self.send(samples, Vec::new()).await
+    }
+
+    /// Writes points to InfluxDB only, bypassing the other sinks.
+    pub async fn write_influxdb(&self, points: Vec<DataPoint>) -> Result<()> {
+        self.send(Vec::new(), points).await
+    }
+
+    async fn send(&self, samples: Vec<Sample>, points: Vec<DataPoint>) -> Result<()> {
+        let queue = &self.sender.0;
+        if queue.detached {
+            queue.push(Batch { samples, points, reply: None }).await;
+            return Ok(());
+        }
+
+        let (reply, done) = oneshot::channel();
+        queue.push(Batch { samples, points, reply: Some(reply) }).await;
+        done.await.map_err(|_| anyhow!("Writer task has stopped"))?
+    }
+}
+
+/// Sinks have side effects beyond this process, so none are built for dry
+/// runs.
+fn build_sinks(config: &AppConfig, metrics: &Arc<Metrics>, dry_run: bool) -> Result<Vec<SinkHandle>> {
+    if dry_run {
+        return Ok(Vec::new());
+    }
+
+    Ok(super::build(config)?
+        .into_iter()
+        .map(|sink| SinkHandle::spawn(sink, metrics.clone()))
+        .collect())
+}
+
+/// `None` if InfluxDB is disabled. Dry runs still print line protocol so the
+/// output can be checked either way.
+fn build_influxdb(config: &AppConfig, dry_run: bool) -> Result<Option<InfluxSink>> {
+    if !config.influxdb.enabled && !dry_run {
+        return Ok(None);
+    }
+
+    let buffer = build_write_buffer(config.buffer.as_ref())?;
+    InfluxSink::new(&config.influxdb, buffer, dry_run).map(Some)
+}
+
+fn build_write_buffer(buffer: Option<&BufferConfig>) -> Result<Option<WriteBuffer>> {
+    buffer
+        .map(|b| WriteBuffer::new(&b.path, b.max_size_bytes))
+        .transpose()
+}
+
+/// InfluxDB points held back by `influxdb.batch`.
+#[derive(Default)]
+struct Pending {
+    points: Vec<DataPoint>,
+    writes: u32,
+    since: Option<Instant>,
+}
+
+struct WriterTask {
+    config_rx: watch::Receiver<Arc<AppConfig>>,
+    config: Arc<AppConfig>,
+    influxdb: Option<InfluxSink>,
+    pending: Pending,
+    sinks: Vec<SinkHandle>,
+    metrics: Arc<Metrics>,
+    dry_run: bool,
+}
+
+impl WriterTask {
+    fn new(
+        mut config_rx: watch::Receiver<Arc<AppConfig>>,
+        metrics: Arc<Metrics>,
+        dry_run: bool,
+    ) -> Result<Self> {
+        let config = config_rx.borrow_and_update().clone();
+        let influxdb = build_influxdb(&config, dry_run)?;
+        let sinks = build_sinks(&config, &metrics, dry_run)?;
+
+        Ok(WriterTask { config_rx, config, influxdb, pending: Pending::default(), sinks, metrics, dry_run })
+    }
+
+    async fn run(mut self, queue: Arc<Queue>) {
+        loop {
+            let flush_at = self.flush_at();
+            let batch = tokio::select! {
+                batch = queue.pop() => match batch {
+                    Some(batch) => batch,
+                    None => break,
+                },
+                _ = time::sleep_until(flush_at.unwrap_or_else(Instant::now)), if flush_at.is_some() => {
+                    self.flush().await;
+                    continue;
+                }
+            };
+
+            if self.config_rx.has_changed().unwrap_or(false) {
+                let config = self.config_rx.borrow_and_update().clone();
+                self.reload(config).await;
+            }
+
+            let samples: Arc<[Sample]> = batch.samples.into();
+            if !samples.is_empty() {
+                for sink in &self.sinks {
+                    sink.send(samples.clone());
+                }
+            }
+
+            let result = match &mut self.influxdb {
+                Some(influxdb) => {
+                    let mut points: Vec<DataPoint> = samples.iter().map(|sample| influxdb.data_point(sample)).collect();
+                    points.extend(batch.points);
+
+                    match &self.config.influxdb.batch {
+                        Some(batching) => {
+                            self.pending.points.extend(points);
+                            self.pending.writes += 1;
+                            self.pending.since.get_or_insert_with(Instant::now);
+                            if self.pending.writes >= batching.cycles {
+                                self.flush().await;
+                            }
+                            Ok(())
+                        }
+                        None => {
+                            let result = influxdb.write_data_points(points).await;
+                            self.metrics.record_influxdb_write(result.is_ok());
+                            result
+                        }
+                    }
+                }
+                None => Ok(()),
+            };
+            match batch.reply {
+                Some(reply) => {
+                    let _ = reply.send(result);
+                }
+                None => {
+                    if let Err(e) = result {
+                        error!("Failed to write to InfluxDB: {:#}", e);
+                    }
+                }
+            }
+        }
+
+        self.flush().await;
+        for sink in self.sinks {
+            sink.close().await;
+        }
+    }
+
+    /// When the pending points are due by age.
+    fn flush_at(&self) -> Option<Instant> {
+        let batching = self.config.influxdb.batch.as_ref()?;
+        Some(self.pending.since? + Duration::from_secs(batching.max_age_secs))
+    }
+
+    /// Writes the pending points in one request. Without a write buffer to
+    /// take them, the points of a failed write stay pending for the next
+    /// attempt.
+    async fn flush(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        let Some(influxdb) = &self.influxdb else {
+            return;
+        };
+        if pending.points.is_empty() {
+            return;
+        }
+
+        let points = pending.points;
+        let count = points.len();
+        let result = influxdb.write_data_points(points.clone()).await;
+        self.metrics.record_influxdb_write(result.is_ok());
+
+        match result {
+            Ok(()) => info!("Wrote a batch of {} points to InfluxDB", count),
+            Err(e) if self.config.buffer.is_some() => error!("Failed to write a batch of {} points: {:#}", count, e),
+            Err(e) => {
+                let max_points = self.config.influxdb.batch.as_ref().map_or(0, |batching| batching.max_points);
+                let dropped = count.saturating_sub(max_points);
+                if dropped > 0 {
+                    warn!("Dropping the {} oldest pending points", dropped);
+                }
+                error!("Failed to write a batch of {} points, retrying with the next one: {:#}", count, e);
+                self.pending.points = points.into_iter().skip(dropped).collect();
+                self.pending.since = Some(Instant::now());
+            }
+        }
+    }
+
+    /// Rebuilds the InfluxDB client, write buffer and sinks only if their
+    /// settings changed. On failure the previous ones stay in use.
+    async fn reload(&mut self, config: Arc<AppConfig>) {
+        if config.influxdb != self.config.influxdb || config.buffer != self.config.buffer {
+            // Pending points go out with the settings they were queued under.
+            self.flush().await;
+            match build_influxdb(&config, self.dry_run) {
+                Ok(influxdb) => {
+                    self.influxdb = influxdb;
+                    info!("InfluxDB settings changed, client rebuilt");
+                }
+                Err(e) => {
+                    error!("Keeping previous InfluxDB client: {:#}", e);
+                    return;
+                }
+            }
+        }
+
+        if super::changed(&self.config, &config) {
+            match build_sinks(&config, &self.metrics, self.dry_run) {
+                Ok(sinks) => {
+                    // Old sinks drain their queues in the background.
+                    for sink in std::mem::replace(&mut self.sinks, sinks) {
+                        tokio::spawn(sink.close());
+                    }
+                    info!("Sink settings changed");
+                }
+                Err(e) => {
+                    error!("Keeping previous sinks: {:#}", e);
+                    return;
+                }
+            }
+        }
+
+        self.config = config;
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(capacity: usize, policy: QueuePolicy) -> Arc<Queue> {
+        Arc::new(Queue {
+            state: Mutex::default(),
+            capacity,
+            policy,
+            detached: true,
+            pushed: Notify::new(),
+            popped: Notify::new(),
+            metrics: Arc::new(Metrics::default()),
+        })
+    }
+
+    /// A batch told apart by its number of points.
+    fn batch(points: usize) -> (Batch, oneshot::Receiver<Result<()>>) {
+        let (reply, done) = oneshot::channel();
+        let points = (0..points)
+            .map(|_| DataPoint::builder("test").field("value", 1i64).build().unwrap())
+            .collect();
+        (Batch { samples: Vec::new(), points, reply: Some(reply) }, done)
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_replies_to_the_dropped_batch() {
+        let queue = queue(2, QueuePolicy::DropOldest);
+        let (first, mut first_done) = batch(1);
+        let (second, mut second_done) = batch(2);
+        let (third, _third_done) = batch(3);
+        queue.push(first).await;
+        queue.push(second).await;
+        assert!(first_done.try_recv().is_err(), "no reply before the queue is full");
+
+        queue.push(third).await;
+        let error = first_done.try_recv().unwrap().unwrap_err();
+        assert!(error.to_string().contains("Dropped from the full write queue"));
+        assert!(second_done.try_recv().is_err());
+
+        assert_eq!(queue.pop().await.unwrap().points.len(), 2);
+        assert_eq!(queue.pop().await.unwrap().points.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn block_waits_for_a_pop() {
+        let queue = queue(1, QueuePolicy::Block);
+        queue.push(batch(1).0).await;
+
+        let pushing = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.push(batch(2).0).await }
+        });
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(!pushing.is_finished(), "push returned while the queue was full");
+
+        assert_eq!(queue.pop().await.unwrap().points.len(), 1);
+        time::timeout(Duration::from_secs(1), pushing).await.unwrap().unwrap();
+        assert_eq!(queue.pop().await.unwrap().points.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn pop_ends_once_closed_and_empty() {
+        let queue = queue(1, QueuePolicy::Block);
+        queue.push(batch(1).0).await;
+        drop(Sender(queue.clone()));
+        assert!(queue.pop().await.is_some());
+        assert!(queue.pop().await.is_none());
+    }
+}
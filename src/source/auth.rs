@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Authentication for requests to a source's API, selected by its `type`
+/// key.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AuthConfig {
+    /// OAuth2 client credentials grant.
+    Oauth2(OAuth2Config),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct OAuth2Config {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: Option<String>,
+    /// Fetch a new token this long before the current one expires.
+    #[serde(default = "default_refresh_margin_secs")]
+    pub refresh_margin_secs: u64,
+}
+
+fn default_refresh_margin_secs() -> u64 {
+    60
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+struct Token {
+    value: String,
+    /// `None` if the server did not say when the token expires; it is then
+    /// only replaced once the API rejects it.
+    refresh_at: Option<Instant>,
+}
+
+/// Obtains bearer tokens and caches them between requests.
+pub struct OAuth2 {
+    client: reqwest::Client,
+    config: OAuth2Config,
+    token: Mutex<Option<Token>>,
+}
+
+impl OAuth2 {
+    pub fn new(client: reqwest::Client, config: OAuth2Config) -> Self {
+        OAuth2 { client, config, token: Mutex::new(None) }
+    }
+
+    /// The cached token, or a new one if it is missing or about to expire.
+    pub async fn token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref()
+            && token.refresh_at.is_none_or(|at| Instant::now() < at)
+        {
+            return Ok(token.value.clone());
+        }
+
+        let fresh = self.request_token().await?;
+        let value = fresh.value.clone();
+        *token = Some(fresh);
+        Ok(value)
+    }
+
+    /// Drops the cached token so the next request fetches a new one.
+    pub async fn invalidate(&self) {
+        *self.token.lock().await = None;
+    }
+
+    async fn request_token(&self) -> Result<Token> {
+        let mut form = vec![("grant_type", "client_credentials")];
+        if let Some(scope) = &self.config.scope {
+            form.push(("scope", scope));
+        }
+
+        let response: TokenResponse = self.client.post(&self.config.token_url)
+            .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+            .form(&form)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .context("OAuth2 token request failed")?
+            .json()
+            .await
+            .context("Failed to parse OAuth2 token response")?;
+
+        let margin = Duration::from_secs(self.config.refresh_margin_secs);
+        let refresh_at = response.expires_in
+            .map(|secs| Instant::now() + Duration::from_secs(secs).saturating_sub(margin));
+
+        Ok(Token { value: response.access_token, refresh_at })
+    }
+}
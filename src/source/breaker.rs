@@ -0,0 +1,84 @@
+use crate::config::CircuitBreakerConfig;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CircuitState {
+    /// Fetching normally.
+    Closed,
+    /// Too many consecutive failures; no requests until the cool-down ends.
+    Open,
+    /// The cool-down has ended and a single trial fetch is allowed.
+    HalfOpen,
+}
+
+impl CircuitState {
+    /// Value of the `msparking_circuit_state` gauge.
+    pub fn as_gauge(self) -> u8 {
+        match self {
+            CircuitState::Closed => 0,
+            CircuitState::Open => 1,
+            CircuitState::HalfOpen => 2,
+        }
+    }
+}
+
+/// Tracks consecutive fetch failures of one source and decides whether the
+/// next fetch may go out.
+#[derive(Default)]
+pub struct CircuitBreaker {
+    failures: u32,
+    open_until: Option<Instant>,
+    half_open: bool,
+}
+
+impl CircuitBreaker {
+    pub fn state(&self) -> CircuitState {
+        if self.open_until.is_some() {
+            CircuitState::Open
+        } else if self.half_open {
+            CircuitState::HalfOpen
+        } else {
+            CircuitState::Closed
+        }
+    }
+
+    /// Time left until an open circuit allows a trial fetch.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.open_until.map(|until| until.saturating_duration_since(Instant::now()))
+    }
+
+    /// Whether a fetch may be sent now. An open circuit turns half-open once
+    /// its cool-down has passed.
+    pub fn allow(&mut self) -> bool {
+        match self.open_until {
+            Some(until) if Instant::now() < until => false,
+            Some(_) => {
+                self.open_until = None;
+                self.half_open = true;
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Records the outcome of a fetch. A failed trial fetch reopens the
+    /// circuit right away.
+    pub fn record(&mut self, config: &CircuitBreakerConfig, success: bool) {
+        if success {
+            self.failures = 0;
+            self.half_open = false;
+            return;
+        }
+
+        self.failures += 1;
+        if self.half_open || self.failures >= config.failure_threshold {
+            self.open_until = Some(Instant::now() + Duration::from_secs(config.cooldown_secs));
+            self.half_open = false;
+        }
+    }
+
+    /// Consecutive failed fetches so far.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+}
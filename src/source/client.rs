@@ -0,0 +1,68 @@
+use super::RateLimited;
+use super::auth::{AuthConfig, OAuth2};
+use crate::archive::Archive;
+use crate::config::SourceConfig;
+use anyhow::{Context, Result};
+use reqwest::StatusCode;
+use tracing::warn;
+
+/// Requests to a source's API, with its HTTP client settings and
+/// authentication applied.
+pub struct ApiClient {
+    client: reqwest::Client,
+    url: String,
+    oauth2: Option<OAuth2>,
+    archive: Option<Archive>,
+}
+
+impl ApiClient {
+    pub fn new(config: &SourceConfig, archive: Option<Archive>) -> Result<Self> {
+        let client = super::http_client(config)?;
+        let oauth2 = match &config.auth {
+            Some(AuthConfig::Oauth2(oauth2)) => Some(OAuth2::new(super::token_client(config)?, oauth2.clone())),
+            None => None,
+        };
+
+        Ok(ApiClient { client, url: config.url.clone(), oauth2, archive })
+    }
+    
+    /// Reads the body of a response from [`Self::get`], archiving it before
+    /// it is parsed so responses that fail to parse are kept too.
+    pub async fn text(&self, response: reqwest::Response) -> Result<String> {
+        let body = response.text().await.context("Failed to read API response")?;
+        if let Some(archive) = &self.archive {
+            archive.save(&body);
+        }
+        Ok(body)
+    }
+
+    /// GETs the source URL. If the API rejects the cached access token, a
+    /// fresh one is obtained and the request retried once. Rate limit
+    /// responses fail with [`RateLimited`].
+    pub async fn get(&self) -> Result<reqwest::Response> {
+        let mut response = self.send().await?;
+
+        if response.status() == StatusCode::UNAUTHORIZED
+            && let Some(oauth2) = &self.oauth2
+        {
+            warn!("API rejected the access token, requesting a new one");
+            oauth2.invalidate().await;
+            response = self.send().await?;
+        }
+
+        if let Some(limited) = RateLimited::from_response(&response) {
+            return Err(limited.into());
+        }
+
+        response.error_for_status().context("API returned an error status")
+    }
+
+    async fn send(&self) -> Result<reqwest::Response> {
+        let mut request = self.client.get(&self.url);
+        if let Some(oauth2) = &self.oauth2 {
+            request = request.bearer_auth(oauth2.token().await?);
+        }
+
+        request.send().await.context("Failed to send request")
+    }
+}
@@ -0,0 +1,158 @@
+use super::{ApiClient, Archived, AreaData, Fetched, Source, SourceType, xml};
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Where the `generic_array`, `keyvalue` and `xml` formats find their
+/// data. Paths are dot-separated, like those of `fields`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MappingConfig {
+    /// Path to the list (or, for `keyvalue`, the object) of areas; the
+    /// whole response if empty.
+    #[serde(default)]
+    pub areas: String,
+    /// Key of the area code within each entry of a `generic_array` or `xml`
+    /// list.
+    #[serde(default = "default_area_code")]
+    pub area_code: String,
+    /// Key of the free spaces within each entry of a `generic_array` or
+    /// `xml` list.
+    #[serde(default = "default_free_spaces")]
+    pub free_spaces: String,
+    /// Path to the time the data was generated at, if the API sends one.
+    pub date: Option<String>,
+}
+
+impl Default for MappingConfig {
+    fn default() -> Self {
+        MappingConfig {
+            areas: String::new(),
+            area_code: default_area_code(),
+            free_spaces: default_free_spaces(),
+            date: None,
+        }
+    }
+}
+
+fn default_area_code() -> String {
+    "area_code".to_string()
+}
+
+fn default_free_spaces() -> String {
+    "free_spaces".to_string()
+}
+
+/// APIs whose layout is described by a [`MappingConfig`] rather than code:
+/// `generic_array` reads a list of objects such as
+/// `[{"area_code": 12, "free_spaces": 80}]`, `keyvalue` an object mapping
+/// area codes to free spaces such as `{"12": 80}`. `xml` reads a list of
+/// elements like `generic_array`, after converting the document to JSON.
+pub struct GenericSource {
+    client: ApiClient,
+    kind: SourceType,
+    mapping: MappingConfig,
+    fields: BTreeMap<String, String>,
+}
+
+impl GenericSource {
+    pub fn new(client: ApiClient, kind: SourceType, mapping: MappingConfig, fields: BTreeMap<String, String>) -> Self {
+        GenericSource { client, kind, mapping, fields }
+    }
+}
+
+#[async_trait]
+impl Source for GenericSource {
+    async fn fetch(&self) -> Result<Fetched> {
+        let response = self.client.get().await?;
+        let status = response.status().as_u16();
+
+        let body = self.client.text(response).await?;
+        let archived = parse(self.kind, &self.mapping, &self.fields, &decode(self.kind, &body)?)?;
+
+        Ok(Fetched {
+            areas: archived.areas,
+            status: Some(status),
+            date: archived.date,
+        })
+    }
+}
+
+pub(super) fn decode(kind: SourceType, body: &str) -> Result<Value> {
+    match kind {
+        SourceType::Xml => xml::to_json(body),
+        _ => serde_json::from_str(body).context("Failed to parse API response"),
+    }
+}
+
+pub(super) fn parse(
+    kind: SourceType,
+    mapping: &MappingConfig,
+    fields: &BTreeMap<String, String>,
+    body: &Value,
+) -> Result<Archived> {
+    let data = super::lookup(body, &mapping.areas)
+        .with_context(|| format!("No {:?} in the API response", mapping.areas))?;
+
+    let areas = match kind {
+        SourceType::KeyValue => data.as_object()
+            .context("Expected an object of areas in the API response")?
+            .iter()
+            .map(|(area_code, free_spaces)| {
+                Ok(AreaData {
+                    area_code: area_code.parse().with_context(|| format!("Invalid area code {:?}", area_code))?,
+                    area_free_space_num: integer(free_spaces)
+                        .with_context(|| format!("Invalid free spaces for area {}", area_code))?,
+                    fields: BTreeMap::new(),
+                })
+            })
+            .collect::<Result<_>>()?,
+        _ => entries(data)?
+            .into_iter()
+            .map(|entry| {
+                let area_code = entry.get(&mapping.area_code)
+                    .and_then(integer)
+                    .with_context(|| format!("Area without a valid {:?}", mapping.area_code))?;
+                let free_spaces = entry.get(&mapping.free_spaces)
+                    .and_then(integer)
+                    .with_context(|| format!("Invalid {:?} for area {}", mapping.free_spaces, area_code))?;
+
+                Ok(AreaData {
+                    area_code: i32::try_from(area_code).map_err(|_| anyhow!("Area code {} out of range", area_code))?,
+                    area_free_space_num: free_spaces,
+                    fields: super::extract_fields(fields, entry),
+                })
+            })
+            .collect::<Result<_>>()?,
+    };
+
+    let date = mapping.date.as_deref()
+        .and_then(|path| super::lookup(body, path))
+        .map(|date| match date {
+            Value::String(date) => date.clone(),
+            other => other.to_string(),
+        });
+
+    Ok(Archived { areas, date })
+}
+
+fn entries(data: &Value) -> Result<Vec<&Value>> {
+    match data {
+        Value::Array(entries) => Ok(entries.iter().collect()),
+        // A list of one comes out of XML as a single element.
+        Value::Object(_) => Ok(vec![data]),
+        _ => Err(anyhow!("Expected a list of areas in the API response")),
+    }
+}
+
+/// Integers may also come as strings, and whole numbers as floats.
+fn integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(number) => number.as_i64().or_else(|| {
+            number.as_f64().filter(|n| n.fract() == 0.0).map(|n| n as i64)
+        }),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,83 @@
+use super::{ApiClient, Archived, AreaData, Fetched, Source};
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use scraper::{Html, Selector};
+use std::collections::BTreeMap;
+use tracing::warn;
+
+/// Reads free spaces from a status web page, with one CSS selector per
+/// area. The first number in the selected element's text is taken, so
+/// `80 free` and `Free: 80` both work.
+pub struct HtmlSource {
+    client: ApiClient,
+    selectors: Vec<(i32, Selector)>,
+}
+
+impl HtmlSource {
+    pub fn new(client: ApiClient, selectors: &BTreeMap<i32, String>) -> Result<Self> {
+        Ok(HtmlSource { client, selectors: compile(selectors)? })
+    }
+}
+
+#[async_trait]
+impl Source for HtmlSource {
+    async fn fetch(&self) -> Result<Fetched> {
+        let response = self.client.get().await?;
+        let status = response.status().as_u16();
+
+        let body = self.client.text(response)
+            .await
+            .context("Failed to read status page")?;
+
+        Ok(Fetched {
+            areas: parse(&self.selectors, &body),
+            status: Some(status),
+            date: None,
+        })
+    }
+}
+
+fn compile(selectors: &BTreeMap<i32, String>) -> Result<Vec<(i32, Selector)>> {
+    selectors.iter()
+        .map(|(&area_code, selector)| {
+            let compiled = Selector::parse(selector)
+                .map_err(|e| anyhow!("Invalid CSS selector for area {}: {}", area_code, e))?;
+            Ok((area_code, compiled))
+        })
+        .collect()
+}
+
+/// Areas whose element is missing or holds no number are left out.
+fn parse(selectors: &[(i32, Selector)], body: &str) -> Vec<AreaData> {
+    let document = Html::parse_document(body);
+
+    selectors.iter()
+        .filter_map(|(area_code, selector)| {
+            let Some(element) = document.select(selector).next() else {
+                warn!("No element on the status page for area {}", area_code);
+                return None;
+            };
+            let text: String = element.text().collect();
+            let Some(free_spaces) = first_number(&text) else {
+                warn!("No number in the status page text for area {}: {:?}", area_code, text.trim());
+                return None;
+            };
+
+            Some(AreaData {
+                area_code: *area_code,
+                area_free_space_num: free_spaces,
+                fields: BTreeMap::new(),
+            })
+        })
+        .collect()
+}
+
+fn first_number(text: &str) -> Option<i64> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let digits: String = text[start..].chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+pub(super) fn parse_archived(body: &str, selectors: &BTreeMap<i32, String>) -> Result<Archived> {
+    Ok(Archived { areas: parse(&compile(selectors)?, body), date: None })
+}
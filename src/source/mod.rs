@@ -0,0 +1,255 @@
+pub mod auth;
+mod breaker;
+mod client;
+mod generic;
+mod html;
+mod msparking;
+mod xml;
+
+pub use breaker::{CircuitBreaker, CircuitState};
+pub use client::ApiClient;
+pub use generic::MappingConfig;
+
+use crate::archive::Archive;
+use crate::config::{RetryConfig, SourceConfig};
+use crate::http;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::time;
+use tracing::warn;
+
+/// Upstream API format of a source, selected by its `type` (or `format`)
+/// key.
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceType {
+    /// The suzhouparking `GetData` endpoint.
+    #[default]
+    Msparking,
+    /// A list of area objects, laid out as described by `mapping`.
+    GenericArray,
+    /// An object mapping area codes to free spaces.
+    #[serde(rename = "keyvalue")]
+    KeyValue,
+    /// An XML document with a list of area elements, located by `mapping`.
+    Xml,
+    /// A status web page, read with one CSS selector per area.
+    Html,
+}
+
+/// An upstream API reporting free spaces per area. Implementations only
+/// fetch and decode; retries, caching and maintenance windows are handled
+/// by the scraper for every source alike.
+#[async_trait]
+pub trait Source: Send + Sync {
+    async fn fetch(&self) -> Result<Fetched>;
+}
+
+/// Areas returned by a single fetch.
+pub struct Fetched {
+    pub areas: Vec<AreaData>,
+    /// Response status, for sources fetched over HTTP.
+    pub status: Option<u16>,
+    /// When the API says the data was generated, as sent.
+    pub date: Option<String>,
+}
+
+/// A previously saved API response, as read by `msparking backfill`.
+pub struct Archived {
+    pub areas: Vec<AreaData>,
+    /// When the API says the data was generated, as sent.
+    pub date: Option<String>,
+}
+
+pub fn parse_archived(config: &SourceConfig, body: &str) -> Result<Archived> {
+    match config.kind {
+        SourceType::Msparking => msparking::parse_archived(body, &config.fields),
+        SourceType::GenericArray | SourceType::KeyValue | SourceType::Xml => {
+            let body = generic::decode(config.kind, body)?;
+            generic::parse(config.kind, &config.mapping, &config.fields, &body)
+        }
+        SourceType::Html => html::parse_archived(body, &config.selectors),
+    }
+}
+
+/// The API asked us to slow down, with a 429 response or a 503 carrying a
+/// `Retry-After` header.
+#[derive(Debug)]
+pub struct RateLimited {
+    pub status: u16,
+    pub retry_after: Option<Duration>,
+}
+
+impl RateLimited {
+    pub fn from_response(response: &reqwest::Response) -> Option<Self> {
+        let retry_after = response.headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+
+        match response.status() {
+            StatusCode::TOO_MANY_REQUESTS => {}
+            StatusCode::SERVICE_UNAVAILABLE if retry_after.is_some() => {}
+            _ => return None,
+        }
+
+        Some(RateLimited { status: response.status().as_u16(), retry_after })
+    }
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "API rate limit hit (HTTP {})", self.status)?;
+        if let Some(retry_after) = self.retry_after {
+            write!(f, ", retry after {}s", retry_after.as_secs())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (at.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+pub fn rate_limited(error: &anyhow::Error) -> Option<&RateLimited> {
+    error.chain().find_map(|e| e.downcast_ref::<RateLimited>())
+}
+
+/// HTTP status of the response behind a failed fetch, if there was one.
+pub fn http_status(error: &anyhow::Error) -> Option<u16> {
+    error.chain()
+        .find_map(|e| e.downcast_ref::<reqwest::Error>())
+        .and_then(|e| e.status())
+        .map(|status| status.as_u16())
+        .or_else(|| rate_limited(error).map(|limited| limited.status))
+}
+
+/// Builds the client for a source, saving its responses to `archive` if
+/// given.
+pub fn build(config: &SourceConfig, archive: Option<Archive>) -> Result<Box<dyn Source>> {
+    let client = ApiClient::new(config, archive)?;
+    Ok(match config.kind {
+        SourceType::Msparking => Box::new(msparking::MsparkingSource::new(client, config.fields.clone())),
+        SourceType::GenericArray | SourceType::KeyValue | SourceType::Xml => Box::new(generic::GenericSource::new(
+            client,
+            config.kind,
+            config.mapping.clone(),
+            config.fields.clone(),
+        )),
+        SourceType::Html => Box::new(html::HtmlSource::new(client, &config.selectors)?),
+    })
+}
+
+/// HTTP client with the source's timeouts, proxy, TLS settings and headers.
+/// Sources keep it across cycles so connections to the API are reused.
+pub fn http_client(config: &SourceConfig) -> Result<reqwest::Client> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &config.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("Invalid header name {:?}", name))?;
+        // The value may be a secret, so keep it out of the error.
+        let value = HeaderValue::from_str(value)
+            .with_context(|| format!("Invalid value for header {}", name))?;
+        headers.insert(name, value);
+    }
+    
+    client_builder(config)?
+        .default_headers(headers)
+        .build()
+        .with_context(|| format!("Failed to build HTTP client for source {}", config.name))
+}
+
+/// HTTP client for the source's OAuth2 token endpoint: the same timeouts,
+/// proxy and TLS settings, but none of the `headers`, which may hold
+/// credentials meant for the API only.
+pub fn token_client(config: &SourceConfig) -> Result<reqwest::Client> {
+    client_builder(config)?
+        .build()
+        .with_context(|| format!("Failed to build token HTTP client for source {}", config.name))
+}
+
+fn client_builder(config: &SourceConfig) -> Result<reqwest::ClientBuilder> {
+    let builder = http::with_proxy(reqwest::Client::builder(), config.proxy_url.as_deref())?;
+    Ok(http::with_tls(builder, config.tls.as_ref())?
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(config.read_timeout_secs))
+        .timeout(Duration::from_secs(config.request_timeout_secs)))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AreaData {
+    #[serde(rename = "areaCode")]
+    pub area_code: i32,
+    #[serde(rename = "areaFreeSpaceNum")]
+    pub area_free_space_num: i64,
+    /// Extra values picked from the area's object by the source's `fields`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, f64>,
+}
+
+/// Follows a dot-separated path into `value`; an empty path is `value`
+/// itself.
+fn lookup<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    path.split('.').try_fold(value, |value, segment| match value {
+        serde_json::Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+        _ => value.get(segment),
+    })
+}
+
+/// Looks up each of `fields` (field name to dot-separated path, with
+/// numeric segments indexing arrays) in an area's object. Finite numbers
+/// and numeric strings are kept; missing paths, NaN, infinities and other
+/// values are left out, as InfluxDB rejects non-finite fields.
+pub fn extract_fields(fields: &BTreeMap<String, String>, area: &serde_json::Value) -> BTreeMap<String, f64> {
+    fields.iter()
+        .filter_map(|(name, path)| {
+            let number = match lookup(area, path)? {
+                serde_json::Value::Number(number) => number.as_f64(),
+                serde_json::Value::String(text) => text.trim().parse::<f64>().ok(),
+                _ => None,
+            }
+            .filter(|number| number.is_finite())?;
+            Some((name.clone(), number))
+        })
+        .collect()
+}
+
+/// Fetches from `source`, retrying with backoff as configured. Rate
+/// limited fetches are not retried.
+pub async fn fetch_parking_data(source: &dyn Source, retry: &RetryConfig) -> Result<Fetched> {
+    let max_attempts = retry.max_attempts.max(1);
+    let mut attempt = 1;
+    
+    loop {
+        match source.fetch().await {
+            Ok(fetched) => return Ok(fetched),
+            Err(e) if rate_limited(&e).is_some() => return Err(e),
+            Err(e) if attempt < max_attempts => {
+                let delay = retry.backoff(attempt);
+                warn!("Fetch attempt {}/{} failed: {:#}. Retrying in {:?}", attempt, max_attempts, e, delay);
+                time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(e.context(format!("Giving up after {} attempts", max_attempts)));
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use super::{ApiClient, Archived, AreaData, Fetched, Source};
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ApiResponse {
+    success: bool,
+    /// Kept as JSON until `areas`, so `fields` can pick any value.
+    #[serde(rename = "msparkingData")]
+    msparking_data: Vec<serde_json::Value>,
+    date: String,
+}
+
+pub struct MsparkingSource {
+    client: ApiClient,
+    fields: BTreeMap<String, String>,
+}
+
+impl MsparkingSource {
+    pub fn new(client: ApiClient, fields: BTreeMap<String, String>) -> Self {
+        MsparkingSource { client, fields }
+    }
+}
+
+#[async_trait]
+impl Source for MsparkingSource {
+    async fn fetch(&self) -> Result<Fetched> {
+        let response = self.client.get().await?;
+        let status = response.status().as_u16();
+
+        let body = self.client.text(response).await?;
+        let data = serde_json::from_str::<ApiResponse>(&body)
+            .context("Failed to parse API response")?;
+
+        Ok(Fetched {
+            date: Some(data.date.clone()),
+            areas: data.areas(&self.fields)?,
+            status: Some(status),
+        })
+    }
+}
+
+impl ApiResponse {
+    fn areas(self, fields: &BTreeMap<String, String>) -> Result<Vec<AreaData>> {
+        if !self.success {
+            return Err(anyhow!("API returned unsuccessful response"));
+        }
+        self.msparking_data
+            .iter()
+            .map(|raw| {
+                let mut area = AreaData::deserialize(raw).context("Invalid area in API response")?;
+                area.fields = super::extract_fields(fields, raw);
+                Ok(area)
+            })
+            .collect()
+    }
+}
+
+pub(super) fn parse_archived(body: &str, fields: &BTreeMap<String, String>) -> Result<Archived> {
+    let data: ApiResponse = serde_json::from_str(body).context("Failed to parse API response")?;
+    let date = Some(data.date.clone());
+    Ok(Archived { areas: data.areas(fields)?, date })
+}
@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Converts an XML response into JSON so the `mapping` paths of the generic
+/// formats apply to it. The root element becomes the top-level object,
+/// child elements are keyed by their name without namespace prefix,
+/// attributes by `@name`, and repeated elements turn into a list. Elements
+/// holding only text become that text.
+pub(super) fn to_json(body: &str) -> Result<Value> {
+    let Xml(value) = quick_xml::de::from_str(body).context("Failed to parse XML response")?;
+    Ok(value)
+}
+
+struct Xml(Value);
+
+impl<'de> Deserialize<'de> for Xml {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(XmlVisitor).map(Xml)
+    }
+}
+
+struct XmlVisitor;
+
+impl<'de> Visitor<'de> for XmlVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an XML element")
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> Result<Value, E> {
+        Ok(Value::String(text.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, text: String) -> Result<Value, E> {
+        Ok(Value::String(text))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(Xml(item)) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut object = Map::new();
+        while let Some((key, Xml(value))) = map.next_entry::<String, Xml>()? {
+            match object.get_mut(&key) {
+                Some(Value::Array(items)) => items.push(value),
+                Some(first) => *first = Value::Array(vec![first.take(), value]),
+                None => {
+                    object.insert(key, value);
+                }
+            }
+        }
+
+        if object.len() == 1
+            && let Some(text) = object.remove("$text")
+        {
+            return Ok(text);
+        }
+        Ok(Value::Object(object))
+    }
+}
@@ -0,0 +1,52 @@
+use crate::config::AppConfig;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{info, warn};
+
+/// Whether `READY=1` has been sent.
+static READY: AtomicBool = AtomicBool::new(false);
+
+/// Reports a finished cycle to systemd under `Type=notify`. The first
+/// successful cycle of any source sends `READY=1`, and every cycle pings the
+/// watchdog, so a wedged scrape loop gets the service restarted. Does
+/// nothing when not started by systemd.
+pub fn cycle_finished(success: bool) {
+    let mut states = vec![sd_notify::NotifyState::Watchdog];
+    let first_success = success && !READY.swap(true, Ordering::Relaxed);
+    if first_success {
+        states.push(sd_notify::NotifyState::Ready);
+    }
+
+    if let Err(e) = sd_notify::notify(&states) {
+        warn!("Failed to notify systemd: {}", e);
+    } else if first_success && std::env::var_os("NOTIFY_SOCKET").is_some() {
+        info!("Notified systemd that the service is ready");
+    }
+}
+
+pub fn stopping() {
+    let _ = sd_notify::notify(&[sd_notify::NotifyState::Stopping]);
+}
+
+/// Warns if no source cycles often enough to ping the watchdog within half
+/// of `WatchdogSec`. Cycles of all sources count, so the shortest interval
+/// is what matters; cron schedules are not considered.
+pub fn check_watchdog(config: &AppConfig) {
+    let Some(timeout) = sd_notify::watchdog_enabled() else {
+        return;
+    };
+
+    let shortest = config.sources()
+        .filter(|source| source.schedule.is_none())
+        .map(|source| source.longest_interval_secs())
+        .min();
+    match shortest {
+        Some(secs) if secs <= timeout.as_secs() / 2 => {
+            info!("systemd watchdog enabled with a {:?} timeout", timeout);
+        }
+        _ => warn!(
+            "systemd watchdog timeout of {:?} is not at least twice the shortest scraping interval, \
+             the service may be restarted while healthy",
+            timeout,
+        ),
+    }
+}
@@ -0,0 +1,107 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Estimates when each area runs out of free spaces from how fast it filled
+/// over the last `window_secs`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct UntilFullConfig {
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    /// Estimates further out are clamped to this and marked invalid.
+    #[serde(default = "default_max_secs")]
+    pub max_secs: u64,
+}
+
+fn default_window_secs() -> u64 {
+    900
+}
+
+fn default_max_secs() -> u64 {
+    86400
+}
+
+/// Seconds until an area has no free spaces left at its current fill rate.
+#[derive(Debug, Clone, Copy)]
+pub struct UntilFull {
+    pub secs: f64,
+    /// False if the area is not filling up, or would take longer than
+    /// `max_secs`, in which case `secs` is `max_secs`.
+    pub valid: bool,
+}
+
+/// Recently scraped free spaces per area of one source, oldest first. Backs
+/// `delta_spaces`, the time until full and the trend alerts.
+#[derive(Default)]
+pub struct Recent {
+    areas: HashMap<i32, VecDeque<(DateTime<Utc>, i64)>>,
+}
+
+impl Recent {
+    /// Adds a value, forgetting those older than `keep` except for the one
+    /// before it.
+    pub fn record(&mut self, area_code: i32, at: DateTime<Utc>, value: i64, keep: Duration) {
+        let values = self.areas.entry(area_code).or_default();
+        values.push_back((at, value));
+
+        let cutoff = at - TimeDelta::from_std(keep).unwrap_or(TimeDelta::MAX);
+        while values.len() > 2 && values[0].0 < cutoff {
+            values.pop_front();
+        }
+    }
+
+    /// Change in free spaces between the last two values, per minute.
+    pub fn delta_per_minute(&self, area_code: i32) -> Option<f64> {
+        let values = self.areas.get(&area_code)?;
+        let (now, value) = *values.back()?;
+        let (at, previous) = *values.get(values.len().checked_sub(2)?)?;
+
+        let minutes = (now - at).num_milliseconds() as f64 / 60_000.0;
+        if minutes <= 0.0 {
+            return None;
+        }
+
+        Some((value - previous) as f64 / minutes)
+    }
+
+    /// Free spaces lost within `window` up to the latest value, from the
+    /// highest value in that window.
+    pub fn lost_within(&self, area_code: i32, window: Duration) -> Option<i64> {
+        let values = self.areas.get(&area_code)?;
+        let (now, latest) = *values.back()?;
+        let cutoff = now - TimeDelta::from_std(window).ok()?;
+
+        let peak = values.iter()
+            .filter(|(at, _)| *at >= cutoff)
+            .map(|(_, value)| *value)
+            .max()?;
+        Some(peak - latest)
+    }
+
+    /// Estimates the time until the area is full from the change between
+    /// the oldest value within the configured window and the latest one.
+    pub fn until_full(&self, area_code: i32, config: &UntilFullConfig) -> Option<UntilFull> {
+        let values = self.areas.get(&area_code)?;
+        let &(now, latest) = values.back()?;
+        let cutoff = now - TimeDelta::from_std(Duration::from_secs(config.window_secs)).ok()?;
+        let &(at, first) = values.iter().find(|(at, _)| *at >= cutoff)?;
+
+        let secs = (now - at).as_seconds_f64();
+        if secs <= 0.0 {
+            return None;
+        }
+        if latest <= 0 {
+            return Some(UntilFull { secs: 0.0, valid: true });
+        }
+
+        let max = config.max_secs as f64;
+        let filled_per_sec = (first - latest) as f64 / secs;
+        let until_full = latest as f64 / filled_per_sec;
+        Some(if filled_per_sec > 0.0 && until_full <= max {
+            UntilFull { secs: until_full, valid: true }
+        } else {
+            UntilFull { secs: max, valid: false }
+        })
+    }
+}
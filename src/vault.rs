@@ -0,0 +1,182 @@
+use crate::http::{self, TlsConfig};
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Reads secrets such as the InfluxDB token or API credentials from
+/// HashiCorp Vault when the configuration is loaded.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct VaultConfig {
+    /// E.g. `https://vault.example.com:8200`.
+    pub address: String,
+    pub auth: VaultAuth,
+    /// Config keys to set from Vault, each mapped to a secret's API path and
+    /// field, e.g. `"influxdb.token" = "secret/data/msparking#influxdb_token"`.
+    /// KV version 1 and 2 paths both work.
+    #[serde(default)]
+    pub secrets: BTreeMap<String, String>,
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum VaultAuth {
+    Token {
+        token: String,
+    },
+    AppRole {
+        role_id: String,
+        secret_id: String,
+        #[serde(default = "default_approle_mount")]
+        mount: String,
+    },
+}
+
+fn default_approle_mount() -> String {
+    "approle".to_string()
+}
+
+/// A Vault token and what is known about its lease.
+struct Session {
+    config: VaultConfig,
+    client: reqwest::Client,
+    token: String,
+    renewable: bool,
+    ttl: Duration,
+}
+
+/// Kept across reloads so they do not log in again every time, and renewed
+/// by [`renew`].
+static SESSION: Mutex<Option<Session>> = Mutex::const_new(None);
+
+/// Fetches every configured secret, as config keys and their values.
+pub async fn resolve(config: &VaultConfig) -> Result<Vec<(String, String)>> {
+    let mut session = SESSION.lock().await;
+    if session.as_ref().is_none_or(|session| session.config != *config) {
+        *session = Some(login(config).await?);
+    }
+    let session = session.as_ref().unwrap();
+
+    let mut secrets = Vec::new();
+    for (key, reference) in &config.secrets {
+        let (path, field) = reference.split_once('#')
+            .ok_or_else(|| anyhow!("Vault secret for {} needs a field, as in path#field", key))?;
+        let secret = read(session, path)
+            .await
+            .with_context(|| format!("Failed to read {} from Vault", key))?;
+
+        // KV version 2 nests the secret's data one level deeper.
+        let value = secret["data"]["data"].get(field)
+            .or_else(|| secret["data"].get(field))
+            .ok_or_else(|| anyhow!("Vault secret {} has no field {}", path, field))?;
+        let value = match value {
+            Value::String(value) => value.clone(),
+            other => other.to_string(),
+        };
+        secrets.push((key.clone(), value));
+    }
+
+    Ok(secrets)
+}
+
+async fn login(config: &VaultConfig) -> Result<Session> {
+    let builder = reqwest::Client::builder().timeout(Duration::from_secs(30));
+    let client = http::with_tls(builder, config.tls.as_ref())?
+        .build()
+        .context("Failed to build Vault HTTP client")?;
+
+    // Token lookups describe the lease under `data`, logins under `auth`.
+    let (token, ttl, renewable) = match &config.auth {
+        VaultAuth::Token { token } => {
+            let response = request(&client, client.get(url(config, "auth/token/lookup-self")), token).await
+                .context("Vault rejected the token")?;
+            let lease = &response["data"];
+            (token.clone(), lease["ttl"].as_u64(), lease["renewable"].as_bool())
+        }
+        VaultAuth::AppRole { role_id, secret_id, mount } => {
+            let response = client.post(url(config, &format!("auth/{}/login", mount)))
+                .json(&serde_json::json!({ "role_id": role_id, "secret_id": secret_id }))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .context("Vault AppRole login failed")?
+                .json::<Value>()
+                .await
+                .context("Failed to parse Vault login response")?;
+            let lease = &response["auth"];
+            let token = lease["client_token"].as_str()
+                .ok_or_else(|| anyhow!("Vault login response has no client token"))?;
+            (token.to_string(), lease["lease_duration"].as_u64(), lease["renewable"].as_bool())
+        }
+    };
+
+    info!("Logged in to Vault at {}", config.address);
+    Ok(Session {
+        config: config.clone(),
+        client,
+        token,
+        renewable: renewable.unwrap_or(false),
+        ttl: Duration::from_secs(ttl.unwrap_or(0)),
+    })
+}
+
+async fn read(session: &Session, path: &str) -> Result<Value> {
+    let get = session.client.get(url(&session.config, path.trim_start_matches('/')));
+    request(&session.client, get, &session.token).await
+}
+
+async fn request(client: &reqwest::Client, builder: reqwest::RequestBuilder, token: &str) -> Result<Value> {
+    let request = builder.header("X-Vault-Token", token).build()?;
+    client.execute(request)
+        .await
+        .and_then(|r| r.error_for_status())?
+        .json()
+        .await
+        .context("Failed to parse Vault response")
+}
+
+fn url(config: &VaultConfig, path: &str) -> String {
+    format!("{}/v1/{}", config.address.trim_end_matches('/'), path)
+}
+
+/// How often [`renew`] looks for a session to renew while there is none.
+const IDLE_CHECK: Duration = Duration::from_secs(60);
+
+/// Renews the Vault token whenever half of its lease has passed, until
+/// `shutdown`. If renewal fails, the session is dropped so the next reload
+/// logs in again. Tokens without a lease are left alone.
+pub async fn renew(shutdown: CancellationToken) {
+    loop {
+        let wait = match SESSION.lock().await.as_ref() {
+            Some(session) if session.renewable && !session.ttl.is_zero() => session.ttl / 2,
+            _ => IDLE_CHECK,
+        };
+
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = time::sleep(wait) => {}
+        }
+
+        let mut session = SESSION.lock().await;
+        let Some(current) = session.as_mut().filter(|session| session.renewable && !session.ttl.is_zero()) else {
+            continue;
+        };
+        let renewal = current.client.post(url(&current.config, "auth/token/renew-self"));
+        match request(&current.client, renewal, &current.token).await {
+            Ok(response) => {
+                current.ttl = Duration::from_secs(response["auth"]["lease_duration"].as_u64().unwrap_or(0));
+                info!("Renewed Vault token for {:?}", current.ttl);
+            }
+            Err(e) => {
+                warn!("Failed to renew Vault token, logging in again on the next reload: {:#}", e);
+                *session = None;
+            }
+        }
+    }
+}